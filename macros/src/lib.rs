@@ -0,0 +1,109 @@
+//! Procedural macros for `outdir-tempdir`.
+//!
+//! Use these through the `macros` feature of `outdir-tempdir` instead of
+//! depending on this crate directly.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, quote_spanned};
+use syn::spanned::Spanned;
+use syn::{parse_macro_input, Expr, FnArg, ItemFn, Pat, Type};
+
+/// Define a test function that receives a temporary directory.
+///
+/// See `outdir_tempdir::test` for details.
+#[proc_macro_attribute]
+pub fn test(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut builder = None;
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("builder") {
+            builder = Some(meta.value()?.parse::<Expr>()?);
+            Ok(())
+        } else {
+            Err(meta.error("unsupported attribute, expected `builder = <expr>`"))
+        }
+    });
+    parse_macro_input!(attr with parser);
+
+    let function = parse_macro_input!(item as ItemFn);
+    match expand(builder, function) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+fn expand(builder: Option<Expr>, function: ItemFn) -> syn::Result<TokenStream2> {
+    let ItemFn {
+        attrs,
+        vis,
+        mut sig,
+        block,
+    } = function;
+
+    if sig.inputs.len() != 1 {
+        return Err(syn::Error::new(
+            sig.inputs.span(),
+            "expected exactly one argument, such as `dir: TempDir` or `dir: &Path`",
+        ));
+    }
+
+    let Some(FnArg::Typed(input)) = sig.inputs.pop().map(|pair| pair.into_value()) else {
+        return Err(syn::Error::new(
+            sig.ident.span(),
+            "`self` is not supported in test functions",
+        ));
+    };
+
+    if let Pat::Ident(ident) = &*input.pat {
+        if ident.by_ref.is_some() {
+            return Err(syn::Error::new(
+                ident.span(),
+                "`ref` bindings are not supported",
+            ));
+        }
+    }
+
+    let pat = &input.pat;
+    let ty = &input.ty;
+    let name = sig.ident.to_string();
+    let builder = builder.unwrap_or_else(|| {
+        syn::parse_quote!(::outdir_tempdir::TempDir::builder()
+            .cargo_target_tmpdir()
+            .out_dir())
+    });
+
+    let tempdir = quote!(__outdir_tempdir_dir);
+    let value = match &**ty {
+        Type::Reference(reference) if is_path_type(&reference.elem) => {
+            quote_spanned!(ty.span()=> #tempdir.path())
+        }
+        Type::Reference(_) => quote_spanned!(ty.span()=> &#tempdir),
+        _ => quote_spanned!(ty.span()=> #tempdir),
+    };
+
+    Ok(quote! {
+        #[test]
+        #(#attrs)*
+        #vis #sig {
+            let #tempdir = (#builder)
+                .build_with_path(#name)
+                .expect(concat!("failed to create temporary directory for test `", #name, "`"))
+                .keep_on_failure();
+            #[allow(clippy::needless_borrow)]
+            let #pat: #ty = #value;
+            #block
+        }
+    })
+}
+
+/// Returns `true` if the type is `Path` or a path ending in `::Path`.
+fn is_path_type(ty: &Type) -> bool {
+    match ty {
+        Type::Path(path) => path
+            .path
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "Path"),
+        _ => false,
+    }
+}
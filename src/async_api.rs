@@ -0,0 +1,184 @@
+use crate::{trash, Error, PendingRemoval, Result, TempDir, TempDirBuilder};
+use std::io;
+use std::path::Path;
+use tokio::runtime::Handle;
+use tokio::task;
+
+/// Run blocking filesystem work on tokio's blocking thread pool.
+async fn blocking<T, F>(work: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T> + Send + 'static,
+{
+    match task::spawn_blocking(work).await {
+        Ok(result) => result,
+        Err(error) => Err(Error::Io(io::Error::other(error))),
+    }
+}
+
+impl TempDirBuilder {
+    /// Create a randomly named temporary directory without blocking the async runtime.
+    ///
+    /// This is the same as [`TempDirBuilder::build`], but the filesystem work
+    /// runs on tokio's blocking thread pool. When the returned [`TempDir`] is
+    /// dropped inside a tokio runtime, its removal is also offloaded to the
+    /// blocking thread pool.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`TempDirBuilder::build`].
+    pub async fn build_async(self) -> Result<TempDir> {
+        let mut tempdir = blocking(move || self.build()).await?;
+        tempdir.async_drop = true;
+        Ok(tempdir)
+    }
+
+    /// Create a temporary directory with a specified relative path without blocking the async runtime.
+    ///
+    /// See [`TempDirBuilder::build_async`] and [`TempDirBuilder::build_with_path`].
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`TempDirBuilder::build_with_path`].
+    pub async fn build_with_path_async<P: AsRef<Path>>(self, path: P) -> Result<TempDir> {
+        let path = path.as_ref().to_path_buf();
+        let mut tempdir = blocking(move || self.build_with_path(path)).await?;
+        tempdir.async_drop = true;
+        Ok(tempdir)
+    }
+}
+
+impl TempDir {
+    /// Remove the temporary directory now without blocking the async runtime.
+    ///
+    /// This is the same as [`TempDir::close`], but the removal runs on tokio's
+    /// blocking thread pool.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`TempDir::close`].
+    pub async fn close_async(self) -> Result<()> {
+        blocking(move || self.close()).await
+    }
+}
+
+/// Offload removal of a dropped [`TempDir`] to the blocking thread pool of the
+/// current tokio runtime.
+///
+/// Returns `false` if there is no current runtime; the caller should then
+/// remove the directory synchronously. Removal errors cannot be raised as a
+/// panic on the dropping thread, so they are printed to standard error.
+pub(crate) fn offload_removal(tempdir: &TempDir) -> bool {
+    let Ok(handle) = Handle::try_current() else {
+        return false;
+    };
+
+    let offloaded = OffloadedRemoval {
+        removal: Some(tempdir.pending_removal()),
+        deferred: tempdir.deferred,
+    };
+    handle.spawn_blocking(move || offloaded.run());
+
+    true
+}
+
+/// Removal handed to the blocking thread pool.
+///
+/// Tokio drops blocking tasks that have not started when the runtime shuts
+/// down. The removal is then handed to the background worker used by
+/// [`TempDir::autorm_deferred`], which also finishes it at process exit.
+struct OffloadedRemoval {
+    removal: Option<PendingRemoval>,
+    deferred: bool,
+}
+
+impl OffloadedRemoval {
+    fn run(mut self) {
+        let Some(removal) = self.removal.take() else {
+            return;
+        };
+
+        let target = removal.path.clone();
+        if let Err(error) = TempDir::remove_on_drop(removal, self.deferred) {
+            eprintln!(
+                "outdir-tempdir: failed to remove \"{}\": {error}",
+                target.display()
+            );
+        }
+    }
+}
+
+impl Drop for OffloadedRemoval {
+    fn drop(&mut self) {
+        if let Some(removal) = self.removal.take() {
+            trash::defer_removal(removal);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OffloadedRemoval;
+    use crate::{wait_for_deferred_removals, TempDir};
+    use std::fs;
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_build_async_and_close_async() {
+        runtime().block_on(async {
+            let temp = TempDir::builder()
+                .out_dir()
+                .build_with_path_async("foo/bar")
+                .await
+                .expect("failed to create temporary directory asynchronously");
+            let path = temp.path().to_path_buf();
+            fs::write(path.join("data.txt"), "data").unwrap();
+
+            temp.close_async().await.unwrap();
+            assert!(!path.try_exists().unwrap());
+        });
+    }
+
+    #[test]
+    fn test_async_drop_offloads_removal() {
+        let runtime = runtime();
+        let path = runtime.block_on(async {
+            let temp = TempDir::builder()
+                .out_dir()
+                .build_async()
+                .await
+                .expect("failed to create temporary directory asynchronously")
+                .autorm();
+            temp.path().to_path_buf()
+        });
+
+        // Dropping the runtime waits for blocking tasks that have started,
+        // and hands the others to the background worker.
+        drop(runtime);
+        wait_for_deferred_removals();
+        assert!(!path.try_exists().unwrap());
+    }
+
+    #[test]
+    fn test_cancelled_offload_is_deferred() {
+        let temp = TempDir::new();
+        let path = temp.path().to_path_buf();
+        let removal = temp.pending_removal();
+        drop(temp);
+
+        // Dropped without running, as tokio does with blocking tasks that
+        // have not started when the runtime shuts down.
+        drop(OffloadedRemoval {
+            removal: Some(removal),
+            deferred: false,
+        });
+
+        wait_for_deferred_removals();
+        assert!(!path.try_exists().unwrap());
+    }
+}
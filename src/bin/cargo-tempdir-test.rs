@@ -0,0 +1,7 @@
+//! Run `cargo test` with a fresh directory for the temporary data of this run.
+
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    outdir_tempdir::__cargo_tempdir_test_main()
+}
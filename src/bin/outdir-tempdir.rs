@@ -0,0 +1,7 @@
+//! List and clean temporary directories left behind by outdir-tempdir.
+
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    outdir_tempdir::__cli_main()
+}
@@ -1,24 +1,230 @@
-use crate::{Error, Result, TempDir, TempDirRoot};
-use std::path::{Path, PathBuf};
+use crate::event::{self, Event};
+use crate::{DirPermissions, Error, Result, TempDir, TempDirRoot};
+use std::ffi::OsString;
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
 use uuid::Uuid;
 
+/// Prefix of the randomly named top-level directory.
+const DEFAULT_PREFIX: &str = "test-";
+
+/// Environment variable read by [`TempDirBuilder::from_env`].
+///
+/// The value is a comma-separated list of root candidates in fallback order,
+/// for example `env:TMPDIR,cargo-target-tmpdir,out-dir,path:/scratch`.
+/// Each candidate is one of:
+///
+/// * `env:<NAME>`, the path in the environment variable `NAME`
+/// * `path:<PATH>`, an absolute path
+/// * `platform-temp-dir`, the platform temporary directory
+/// * `cargo-target-tmpdir`, runtime `CARGO_TARGET_TMPDIR`
+/// * `out-dir`, the crate's compile-time `OUT_DIR`
+///
+/// Whitespace around each candidate is ignored.
+pub const ROOTS_ENV: &str = "OUTDIR_TEMPDIR_ROOTS";
+
+/// Environment variable holding the run directory in tests started by `cargo tempdir-test`.
+///
+/// Use it with [`TempDirBuilder::env`]. Unless [`ROOTS_ENV`] is already set,
+/// `cargo tempdir-test` also sets it to
+/// `env:OUTDIR_TEMPDIR_RUN_DIR,cargo-target-tmpdir,out-dir`, so builders
+/// created with [`TempDirBuilder::from_env`] use the run directory too.
+pub const RUN_DIR_ENV: &str = "OUTDIR_TEMPDIR_RUN_DIR";
+
+/// Cleanup policy applied to directories created by [`TempDirBuilder`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub enum Cleanup {
+    /// Keep the directory. This is the default, as with [`TempDir::new`].
+    #[default]
+    Keep,
+
+    /// Remove the directory when it is dropped. See [`TempDir::autorm`].
+    Remove,
+
+    /// Remove the directory unless the thread is panicking. See [`TempDir::keep_on_failure`].
+    KeepOnFailure,
+}
+
+/// Settings of a [`TempDirBuilder`] that can be stored or passed to another process.
+///
+/// Created by [`TempDirBuilder::to_config`], and turned back into a builder
+/// with [`TempDirBuilder::from`]. With the `serde` feature, it can be
+/// serialized; root candidates use the grammar of [`ROOTS_ENV`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default, deny_unknown_fields)
+)]
+pub struct TempDirConfig {
+    roots: Vec<TempDirRoot>,
+    prefix: String,
+    mode: Option<u32>,
+    cleanup: Cleanup,
+}
+
+impl TempDirConfig {
+    /// Get the root candidates in fallback order.
+    pub fn roots(&self) -> &[TempDirRoot] {
+        &self.roots
+    }
+
+    /// Get the prefix of the randomly named top-level directory.
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// Get the permissions of the created directories, if set.
+    pub fn mode(&self) -> Option<u32> {
+        self.mode
+    }
+
+    /// Get the cleanup policy.
+    pub fn cleanup(&self) -> Cleanup {
+        self.cleanup
+    }
+}
+
+impl Default for TempDirConfig {
+    /// Try `CARGO_TARGET_TMPDIR` and then `OUT_DIR`, with the `test-` prefix,
+    /// the default permissions and [`Cleanup::Keep`].
+    fn default() -> Self {
+        Self {
+            roots: vec![TempDirRoot::CargoTargetTmp, TempDirRoot::Out],
+            prefix: DEFAULT_PREFIX.to_string(),
+            mode: None,
+            cleanup: Cleanup::Keep,
+        }
+    }
+}
+
+impl From<TempDirConfig> for TempDirBuilder {
+    /// Create a builder with the given settings. The mode is ignored on
+    /// platforms other than Unix.
+    fn from(config: TempDirConfig) -> Self {
+        let mut builder = Self::new().prefix(config.prefix).cleanup(config.cleanup);
+        builder.roots = config.roots;
+        #[cfg(unix)]
+        {
+            builder.mode = config.mode;
+        }
+        builder
+    }
+}
+
+/// Lookup of environment variables, replaced in tests so that they never
+/// modify the process environment.
+type Vars = dyn Fn(&str) -> Option<OsString> + Send + Sync;
+
 /// Builder for selecting temporary directory roots in caller-defined fallback order.
+///
+/// Root candidates are tried in order:
+///
+/// * A candidate that is not available, such as an unset or empty environment
+///   variable, is skipped with [`Event::RootSkipped`].
+/// * A candidate in which the directory cannot be created, for example
+///   because the root does not exist, is skipped with [`Event::RootFailed`].
+/// * A candidate that is available but unsafe, because it is relative, the
+///   filesystem root, the home directory, or world-writable with
+///   [`TempDirBuilder::reject_world_writable_roots`], stops the build with
+///   that error instead of falling back, so that a misconfigured root is
+///   noticed rather than silently ignored.
 pub struct TempDirBuilder {
     roots: Vec<TempDirRoot>,
+    prefix: String,
+    cleanup: Cleanup,
+    reject_world_writable_roots: bool,
+    mode: Option<u32>,
+    removal_retry: Option<(u32, Duration)>,
+    max_bytes: Option<u64>,
+    quota_watchdog: Option<Duration>,
+    quota_exit: bool,
+    vars: Box<Vars>,
 }
 
 impl TempDirBuilder {
     pub(crate) fn new() -> Self {
-        Self { roots: Vec::new() }
+        Self {
+            roots: Vec::new(),
+            prefix: DEFAULT_PREFIX.to_string(),
+            cleanup: Cleanup::Keep,
+            reject_world_writable_roots: false,
+            mode: None,
+            removal_retry: None,
+            max_bytes: None,
+            quota_watchdog: None,
+            quota_exit: false,
+            vars: Box::new(|name| std::env::var_os(name)),
+        }
+    }
+
+    /// Read the variables of `env:` candidates and `CARGO_TARGET_TMPDIR`
+    /// through `var` instead of the process environment.
+    pub(crate) fn vars<F>(mut self, var: F) -> Self
+    where
+        F: Fn(&str) -> Option<OsString> + Send + Sync + 'static,
+    {
+        self.vars = Box::new(var);
+        self
+    }
+
+    /// Create a builder with the root candidates listed in [`ROOTS_ENV`].
+    ///
+    /// If the variable is not set or is empty, the builder tries
+    /// `CARGO_TARGET_TMPDIR` and then `OUT_DIR`.
+    ///
+    /// # Errors
+    ///
+    /// A candidate that does not follow the grammar of [`ROOTS_ENV`] will
+    /// result in an `InvalidRootSpec` error. A value that is not valid Unicode
+    /// will result in an `InvalidRootSpec` error as well.
+    pub fn from_env() -> Result<Self> {
+        Self::from_vars(|name| std::env::var_os(name))
+    }
+
+    /// Same as [`TempDirBuilder::from_env`], but reads [`ROOTS_ENV`] and the
+    /// variables of `env:` candidates through `var`.
+    pub(crate) fn from_vars<F>(var: F) -> Result<Self>
+    where
+        F: Fn(&str) -> Option<OsString> + Send + Sync + 'static,
+    {
+        let value = match var(ROOTS_ENV).map(OsString::into_string) {
+            None => String::new(),
+            Some(Ok(value)) => value,
+            Some(Err(value)) => {
+                return Err(Error::InvalidRootSpec(value.to_string_lossy().into_owned()))
+            }
+        };
+
+        let builder = Self::new().vars(var);
+        if value.trim().is_empty() {
+            return Ok(builder.cargo_target_tmpdir().out_dir());
+        }
+
+        value
+            .split(',')
+            .map(str::trim)
+            .try_fold(builder, |builder, spec| Ok(builder.root(spec.parse()?)))
     }
 
     /// Add the path from the named environment variable as a root candidate.
+    ///
+    /// The path must be absolute. It is canonicalised, and the filesystem root
+    /// and the user's home directory are rejected. See [`TempDirBuilder`] for
+    /// which problems fall back to the next candidate.
     pub fn env<S: Into<String>>(mut self, name: S) -> Self {
         self.roots.push(TempDirRoot::Env(name.into()));
         self
     }
 
     /// Add the platform temporary directory as a root candidate.
+    ///
+    /// The path is validated in the same way as [`TempDirBuilder::env`].
     pub fn platform_temp_dir(mut self) -> Self {
         self.roots.push(TempDirRoot::PlatformTempDir);
         self
@@ -36,9 +242,130 @@ impl TempDirBuilder {
         self
     }
 
+    /// Add an explicit path as a root candidate.
+    ///
+    /// The path is validated in the same way as [`TempDirBuilder::env`].
+    pub fn path<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.roots.push(TempDirRoot::Path(path.into()));
+        self
+    }
+
+    /// Add a root candidate.
+    ///
+    /// This is the same as calling the method for the given variant, for
+    /// example [`TempDirBuilder::env`] for [`TempDirRoot::Env`].
+    pub fn root(mut self, root: TempDirRoot) -> Self {
+        self.roots.push(root);
+        self
+    }
+
+    /// Set the prefix of the randomly named top-level directory.
+    ///
+    /// The default is `test-`, giving `test-<uuid>`. The prefix must not
+    /// contain path separators; otherwise building leads to an `InvalidPath`
+    /// error.
+    pub fn prefix<S: Into<String>>(mut self, prefix: S) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Set the cleanup policy of the created directory.
+    ///
+    /// The default is [`Cleanup::Keep`].
+    pub fn cleanup(mut self, cleanup: Cleanup) -> Self {
+        self.cleanup = cleanup;
+        self
+    }
+
+    /// Reject environment-provided and platform roots that are world-writable
+    /// without the sticky bit.
+    ///
+    /// In such a directory, other users could rename or replace the created
+    /// temporary directory. This check is only performed on Unix.
+    pub fn reject_world_writable_roots(mut self) -> Self {
+        self.reject_world_writable_roots = true;
+        self
+    }
+
+    /// Set the permissions of every directory level created by the builder.
+    ///
+    /// By default, the private top-level `test-<uuid>` directory is created
+    /// with owner-only permissions (`0o700`) and the levels below it follow the
+    /// process umask. The mode is applied exactly, regardless of the umask.
+    ///
+    /// Levels are created owner-only and get the mode once the whole path
+    /// exists, so modes without owner write permission, such as `0o500`, can
+    /// be used to create read-only trees.
+    ///
+    /// Only permission bits are allowed. A mode above `0o7777` makes the build
+    /// fail with an `InvalidMode` error.
+    #[cfg(unix)]
+    pub fn mode(mut self, mode: u32) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Retry failed removal of the created directory.
+    ///
+    /// See [`TempDir::removal_retries`].
+    pub fn removal_retries(mut self, attempts: u32, backoff: Duration) -> Self {
+        self.removal_retry = Some((attempts, backoff));
+        self
+    }
+
+    /// Limit the total size of regular files in the created directory.
+    ///
+    /// The limit is checked by [`TempDir::check_quota`], and periodically in
+    /// the background if [`TempDirBuilder::quota_watchdog`] is also set.
+    pub fn max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Check the quota set by [`TempDirBuilder::max_bytes`] every `interval` on a background thread.
+    ///
+    /// By default, once the quota is exceeded, the error, including the
+    /// largest files, is printed to standard error and recorded, and the
+    /// thread stops checking. This does not stop a writer that never checks;
+    /// use [`TempDirBuilder::exit_on_quota_exceeded`] for that.
+    /// From then on, [`TempDir::check_quota`] returns the error, so code that
+    /// writes in a loop can poll it and stop before it fills the disk. The
+    /// directory is still handled as usual when the [`TempDir`] is dropped or
+    /// closed, then [`TempDir::close`] returns the error and dropping panics
+    /// with it, which fails the test. The thread stops when the [`TempDir`] is
+    /// dropped. Without [`TempDirBuilder::max_bytes`], this has no effect.
+    pub fn quota_watchdog(mut self, interval: Duration) -> Self {
+        self.quota_watchdog = Some(interval);
+        self
+    }
+
+    /// Exit the process as soon as the watchdog finds the quota exceeded.
+    ///
+    /// The error is printed to standard error and the process exits with
+    /// status 101, the same status as a failed test, before a runaway writer
+    /// fills the disk. Destructors do not run, so the directory is left behind
+    /// for inspection and can be removed with `outdir-tempdir clean`. Without
+    /// [`TempDirBuilder::quota_watchdog`], this has no effect.
+    pub fn exit_on_quota_exceeded(mut self) -> Self {
+        self.quota_exit = true;
+        self
+    }
+
+    /// Get the root candidates, prefix, permissions and cleanup policy of this builder.
+    ///
+    /// Other settings, such as quotas and removal retries, are not included.
+    pub fn to_config(&self) -> TempDirConfig {
+        TempDirConfig {
+            roots: self.roots.clone(),
+            prefix: self.prefix.clone(),
+            mode: self.mode,
+            cleanup: self.cleanup,
+        }
+    }
+
     /// Create a randomly named temporary directory.
     pub fn build(self) -> Result<TempDir> {
-        let private_root = PathBuf::from(format!("test-{}", Uuid::new_v4()));
+        let private_root = self.private_root()?;
         self.build_in_private_root(private_root, Path::new(""))
     }
 
@@ -46,7 +373,7 @@ impl TempDirBuilder {
     pub fn build_with_path<P: AsRef<Path>>(self, path: P) -> Result<TempDir> {
         let path = path.as_ref();
         let target = TempDir::cleanse_relative_path(path)?;
-        let private_root = PathBuf::from(format!("test-{}", Uuid::new_v4()));
+        let private_root = self.private_root()?;
 
         if target.as_os_str().is_empty() {
             return Err(Error::InvalidPath(path.to_path_buf()));
@@ -55,6 +382,15 @@ impl TempDirBuilder {
         self.build_in_private_root(private_root, &target)
     }
 
+    fn private_root(&self) -> Result<PathBuf> {
+        let private_root = PathBuf::from(format!("{}{}", self.prefix, Uuid::new_v4()));
+        let mut components = private_root.components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(_)), None) => Ok(private_root),
+            _ => Err(Error::InvalidPath(private_root)),
+        }
+    }
+
     fn build_in_private_root(self, private_root: PathBuf, target: &Path) -> Result<TempDir> {
         let full_target = if target.as_os_str().is_empty() {
             private_root.clone()
@@ -65,23 +401,87 @@ impl TempDirBuilder {
         if self.roots.is_empty() {
             return Err(Error::NoRootCandidatesConfigured);
         }
+        if let Some(mode) = self.mode.filter(|mode| *mode > 0o7777) {
+            return Err(Error::InvalidMode(mode));
+        }
 
+        let permissions = DirPermissions {
+            top: Some(self.mode.unwrap_or(0o700)),
+            nested: self.mode,
+        };
         let mut last_error = None;
 
         for root_kind in self.roots {
-            let Some(root) = TempDir::root_path_if_available(&root_kind) else {
+            let candidate = root_kind.to_string();
+            let Some(root) = TempDir::root_path_with(&root_kind, &*self.vars) else {
+                event::emit(Event::RootSkipped {
+                    candidate: &candidate,
+                });
                 continue;
             };
 
+            let root_failed = |root: &Path, error: &Error| {
+                event::emit(Event::RootFailed {
+                    candidate: &candidate,
+                    root,
+                    error,
+                })
+            };
+
+            let root = match root_kind {
+                TempDirRoot::Env(_) | TempDirRoot::PlatformTempDir | TempDirRoot::Path(_) => {
+                    match TempDir::validate_root(root.clone(), self.reject_world_writable_roots) {
+                        Ok(root) => root,
+                        Err(error) => {
+                            root_failed(&root, &error);
+                            match error {
+                                Error::Io(error) => {
+                                    last_error = Some(error);
+                                    continue;
+                                }
+                                error => return Err(error),
+                            }
+                        }
+                    }
+                }
+                TempDirRoot::Out | TempDirRoot::CargoTargetTmp => root,
+            };
+
             match TempDir::create_in_root_with_removal(
                 target,
                 &full_target,
-                root,
+                root_kind,
+                root.clone(),
                 private_root.clone(),
+                permissions,
             ) {
-                Ok(tempdir) => return Ok(tempdir),
-                Err(Error::Io(error)) => last_error = Some(error),
-                Err(error) => return Err(error),
+                Ok(mut tempdir) => {
+                    if let Some((attempts, backoff)) = self.removal_retry {
+                        tempdir = tempdir.removal_retries(attempts, backoff);
+                    }
+                    if let Some(max_bytes) = self.max_bytes {
+                        if let Err(error) =
+                            tempdir.set_quota(max_bytes, self.quota_watchdog, self.quota_exit)
+                        {
+                            // Do not leave the new directory behind.
+                            let _ = tempdir.close();
+                            return Err(error);
+                        }
+                    }
+                    tempdir = match self.cleanup {
+                        Cleanup::Keep => tempdir,
+                        Cleanup::Remove => tempdir.autorm(),
+                        Cleanup::KeepOnFailure => tempdir.keep_on_failure(),
+                    };
+                    return Ok(tempdir);
+                }
+                Err(error) => {
+                    root_failed(&root, &error);
+                    match error {
+                        Error::Io(error) => last_error = Some(error),
+                        error => return Err(error),
+                    }
+                }
             }
         }
 
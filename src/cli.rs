@@ -0,0 +1,575 @@
+//! Implementation of the `outdir-tempdir` command-line tool.
+
+use crate::lock::DirLock;
+use crate::remove::{remove_tree, RemovalRetry};
+use crate::DiskUsage;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::{Duration, SystemTime};
+use uuid::Uuid;
+
+const USAGE: &str = "\
+Usage: outdir-tempdir [--target-dir <DIR>] [--prefix <PREFIX>]... <COMMAND>
+
+Find temporary directories left behind by outdir-tempdir.
+
+Commands:
+  list                           List leftover directories and their age
+  du                             Show the disk space used by each directory
+  clean [--older-than <AGE>] [--dry-run]
+                                 Remove leftover directories older than <AGE> [default: 1d]
+
+Options:
+  --target-dir <DIR>  Cargo target directory [default: $CARGO_TARGET_DIR or ./target]
+  --prefix <PREFIX>   Prefix of the directory names, may be repeated [default: test-]
+  -h, --help          Print this help
+
+Directories named <PREFIX><uuid> or .trash-<uuid> are searched for directly
+under <target>/*/build/*/out, <target>/*/*/build/*/out, <target>/tmp,
+$CARGO_TARGET_TMPDIR and $TMPDIR. Directories whose lock file is held by a
+running test are never removed.
+
+<AGE> is a number followed by s, m, h, d or w, for example 30m or 1d.
+";
+
+/// Prefix of the directory names created by default.
+const DEFAULT_PREFIX: &str = "test-";
+
+/// Prefix of the directories pending background removal.
+const TRASH_PREFIX: &str = ".trash-";
+
+/// Age of the directories removed by `clean` without `--older-than`.
+const DEFAULT_OLDER_THAN: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug, PartialEq, Eq)]
+enum Command {
+    List,
+    Du,
+    Clean { older_than: Duration, dry_run: bool },
+    Help,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Args {
+    target_dir: Option<PathBuf>,
+    prefixes: Vec<String>,
+    command: Command,
+}
+
+/// A directory matching the naming scheme.
+struct Leftover {
+    root: PathBuf,
+    name: OsString,
+    age: Option<Duration>,
+}
+
+impl Leftover {
+    fn path(&self) -> PathBuf {
+        self.root.join(&self.name)
+    }
+
+    /// Get the lock file taken by tests using the directory, see
+    /// [`TempDir::with_path_locked`](crate::TempDir::with_path_locked).
+    fn lock_path(&self) -> PathBuf {
+        let mut name = self.name.clone();
+        name.push(".lock");
+        self.root.join(name)
+    }
+}
+
+/// Entry point of the `outdir-tempdir` binary.
+pub fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args = match parse_args(&args) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("outdir-tempdir: {message}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+
+    let roots = roots(args.target_dir);
+    match run(
+        args.command,
+        &roots,
+        &args.prefixes,
+        &mut io::stdout().lock(),
+    ) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(error) => {
+            eprintln!("outdir-tempdir: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<Args, String> {
+    let mut target_dir = None;
+    let mut prefixes = Vec::new();
+    let mut command = None;
+    let mut older_than = None;
+    let mut dry_run = false;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => command = Some("help"),
+            "--target-dir" => {
+                let value = args.next().ok_or("`--target-dir` requires a value")?;
+                target_dir = Some(PathBuf::from(value));
+            }
+            "--prefix" => {
+                let value = args.next().ok_or("`--prefix` requires a value")?;
+                if value.is_empty() {
+                    return Err("`--prefix` must not be empty".to_string());
+                }
+                prefixes.push(value.clone());
+            }
+            "--older-than" => {
+                let value = args.next().ok_or("`--older-than` requires a value")?;
+                older_than = Some(parse_age(value)?);
+            }
+            "--dry-run" => dry_run = true,
+            "list" | "du" | "clean" if command.is_none() => command = Some(arg.as_str()),
+            arg => return Err(format!("unexpected argument `{arg}`")),
+        }
+    }
+
+    let command = match command {
+        Some("help") => Command::Help,
+        Some("clean") => Command::Clean {
+            older_than: older_than.unwrap_or(DEFAULT_OLDER_THAN),
+            dry_run,
+        },
+        Some(_) if older_than.is_some() || dry_run => {
+            return Err("`--older-than` and `--dry-run` are only valid for `clean`".to_string())
+        }
+        Some("list") => Command::List,
+        Some("du") => Command::Du,
+        _ => return Err("missing command".to_string()),
+    };
+
+    if prefixes.is_empty() {
+        prefixes.push(DEFAULT_PREFIX.to_string());
+    }
+
+    Ok(Args {
+        target_dir,
+        prefixes,
+        command,
+    })
+}
+
+fn parse_age(value: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid age `{value}`, expected for example `30m` or `1d`");
+    let split = value.len().checked_sub(1).ok_or_else(invalid)?;
+    let (number, unit) = value.split_at(split);
+    let number: u64 = number.parse().map_err(|_| invalid())?;
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+    Ok(Duration::from_secs(number.saturating_mul(seconds)))
+}
+
+/// List every directory searched for leftovers.
+fn roots(target_dir: Option<PathBuf>) -> Vec<PathBuf> {
+    let target_dir = target_dir
+        .or_else(|| std::env::var_os("CARGO_TARGET_DIR").map(PathBuf::from))
+        .unwrap_or_else(|| PathBuf::from("target"));
+
+    let mut roots = search_roots(&target_dir);
+    if let Some(dir) = std::env::var_os("CARGO_TARGET_TMPDIR") {
+        roots.push(PathBuf::from(dir));
+    }
+    roots.push(std::env::temp_dir());
+    roots
+}
+
+/// Run a parsed command. Returns `false` if some directories could not be measured or removed.
+fn run(
+    command: Command,
+    roots: &[PathBuf],
+    prefixes: &[String],
+    out: &mut dyn Write,
+) -> io::Result<bool> {
+    let leftovers = find_leftovers(roots, prefixes);
+    match command {
+        Command::Help => write!(out, "{USAGE}")?,
+        Command::List => {
+            for leftover in &leftovers {
+                writeln!(
+                    out,
+                    "{:>6}  {}",
+                    age(leftover.age),
+                    leftover.path().display()
+                )?;
+            }
+        }
+        Command::Du => return du(&leftovers, out),
+        Command::Clean {
+            older_than,
+            dry_run,
+        } => return clean(&leftovers, older_than, dry_run, out),
+    }
+
+    Ok(true)
+}
+
+/// Print the disk space used by each directory. Returns `false` if some
+/// directories could not be measured; they are shown with an unknown size.
+fn du(leftovers: &[Leftover], out: &mut dyn Write) -> io::Result<bool> {
+    let mut ok = true;
+    let mut total = 0;
+
+    for leftover in leftovers {
+        let size = match DiskUsage::measure(&leftover.path()) {
+            Ok(usage) => {
+                total += usage.allocated_bytes();
+                usage.allocated_bytes().to_string()
+            }
+            Err(error) => {
+                eprintln!(
+                    "outdir-tempdir: failed to measure \"{}\": {error}",
+                    leftover.path().display()
+                );
+                ok = false;
+                "?".to_string()
+            }
+        };
+        writeln!(out, "{:>14}  {}", size, leftover.path().display())?;
+    }
+    writeln!(out, "{total:>14}  total")?;
+
+    Ok(ok)
+}
+
+fn clean(
+    leftovers: &[Leftover],
+    older_than: Duration,
+    dry_run: bool,
+    out: &mut dyn Write,
+) -> io::Result<bool> {
+    let mut ok = true;
+
+    for leftover in leftovers {
+        // Directories with an unknown age are never considered old.
+        match leftover.age {
+            Some(age) if age >= older_than => {}
+            _ => continue,
+        }
+
+        // Keep the lock, if any, until the directory is removed, so that no
+        // test starts using it in the meantime.
+        let _lock = match DirLock::try_exclusive(&leftover.lock_path()) {
+            Ok(Some(lock)) => Some(lock),
+            Ok(None) => {
+                writeln!(out, "skipped {} (in use)", leftover.path().display())?;
+                continue;
+            }
+            Err(error) if error.kind() == io::ErrorKind::NotFound => None,
+            Err(error) => {
+                eprintln!(
+                    "outdir-tempdir: failed to check the lock of \"{}\": {error}",
+                    leftover.path().display()
+                );
+                ok = false;
+                continue;
+            }
+        };
+
+        if dry_run {
+            writeln!(out, "would remove {}", leftover.path().display())?;
+            continue;
+        }
+
+        match remove_tree(
+            &leftover.root,
+            Path::new(&leftover.name),
+            &RemovalRetry::default(),
+        ) {
+            Ok(()) => writeln!(out, "removed {}", leftover.path().display())?,
+            Err(error) => {
+                eprintln!(
+                    "outdir-tempdir: failed to remove \"{}\": {error}",
+                    leftover.path().display()
+                );
+                ok = false;
+            }
+        }
+    }
+
+    Ok(ok)
+}
+
+/// List `<target>/*/build/*/out`, `<target>/*/*/build/*/out` and `<target>/tmp`.
+fn search_roots(target_dir: &Path) -> Vec<PathBuf> {
+    let mut roots = Vec::new();
+
+    // `<target>/<profile>` and `<target>/<triple>/<profile>`.
+    let mut profiles = subdirs(target_dir);
+    profiles.extend(subdirs(target_dir).iter().flat_map(|dir| subdirs(dir)));
+
+    for profile in profiles {
+        for package in subdirs(&profile.join("build")) {
+            let out = package.join("out");
+            if out.is_dir() {
+                roots.push(out);
+            }
+        }
+    }
+
+    let tmp = target_dir.join("tmp");
+    if tmp.is_dir() {
+        roots.push(tmp);
+    }
+
+    roots
+}
+
+fn subdirs(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut dirs: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_dir()))
+        .map(|entry| entry.path())
+        .collect();
+    dirs.sort();
+    dirs
+}
+
+fn find_leftovers(roots: &[PathBuf], prefixes: &[String]) -> Vec<Leftover> {
+    let mut seen = Vec::new();
+    let mut leftovers = Vec::new();
+
+    for root in roots {
+        // The same directory may be reachable as, for example, both
+        // `CARGO_TARGET_TMPDIR` and `<target>/tmp`.
+        let canonical = root.canonicalize().unwrap_or_else(|_| root.clone());
+        if seen.contains(&canonical) {
+            continue;
+        }
+        seen.push(canonical);
+
+        for dir in subdirs(root) {
+            let Some(name) = dir.file_name() else {
+                continue;
+            };
+            if !is_leftover_name(&name.to_string_lossy(), prefixes) {
+                continue;
+            }
+
+            let age = fs::symlink_metadata(&dir)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .and_then(|modified| SystemTime::now().duration_since(modified).ok());
+            leftovers.push(Leftover {
+                root: root.clone(),
+                name: name.to_os_string(),
+                age,
+            });
+        }
+    }
+
+    leftovers
+}
+
+fn is_leftover_name(name: &str, prefixes: &[String]) -> bool {
+    let prefixes = prefixes.iter().map(String::as_str);
+    std::iter::once(TRASH_PREFIX).chain(prefixes).any(|prefix| {
+        name.strip_prefix(prefix)
+            .is_some_and(|uuid| uuid.len() == 36 && Uuid::parse_str(uuid).is_ok())
+    })
+}
+
+fn age(age: Option<Duration>) -> String {
+    let Some(age) = age else {
+        return "?".to_string();
+    };
+
+    let seconds = age.as_secs();
+    match seconds {
+        0..60 => format!("{seconds}s"),
+        60..3600 => format!("{}m", seconds / 60),
+        3600..86400 => format!("{}h", seconds / 3600),
+        _ => format!("{}d", seconds / 86400),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TempDir;
+
+    fn args(args: &[&str]) -> Result<Args, String> {
+        parse_args(&args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(
+            args(&["clean", "--older-than", "1h", "--dry-run"]),
+            Ok(Args {
+                target_dir: None,
+                prefixes: vec!["test-".to_string()],
+                command: Command::Clean {
+                    older_than: Duration::from_secs(3600),
+                    dry_run: true,
+                },
+            })
+        );
+        assert_eq!(
+            args(&["clean"]),
+            Ok(Args {
+                target_dir: None,
+                prefixes: vec!["test-".to_string()],
+                command: Command::Clean {
+                    older_than: Duration::from_secs(86400),
+                    dry_run: false,
+                },
+            })
+        );
+        assert_eq!(
+            args(&["--target-dir", "out", "--prefix", "ci-", "du"]),
+            Ok(Args {
+                target_dir: Some(PathBuf::from("out")),
+                prefixes: vec!["ci-".to_string()],
+                command: Command::Du,
+            })
+        );
+        assert!(args(&[]).is_err());
+        assert!(args(&["list", "--dry-run"]).is_err());
+        assert!(args(&["list", "du"]).is_err());
+        assert!(args(&["clean", "--older-than", "1y"]).is_err());
+        assert!(args(&["clean", "--older-than"]).is_err());
+        assert!(args(&["list", "--prefix", ""]).is_err());
+    }
+
+    #[test]
+    fn test_leftover_names() {
+        let uuid = Uuid::new_v4();
+        let prefixes = ["test-".to_string()];
+        assert!(is_leftover_name(&format!("test-{uuid}"), &prefixes));
+        assert!(is_leftover_name(&format!(".trash-{uuid}"), &prefixes));
+        assert!(!is_leftover_name("test-output", &prefixes));
+        assert!(!is_leftover_name(&format!("ci-{uuid}"), &prefixes));
+        assert!(!is_leftover_name(&format!("test-{uuid}-extra"), &prefixes));
+
+        let prefixes = ["ci-".to_string()];
+        assert!(is_leftover_name(&format!("ci-{uuid}"), &prefixes));
+        assert!(is_leftover_name(&format!(".trash-{uuid}"), &prefixes));
+        assert!(!is_leftover_name(&format!("test-{uuid}"), &prefixes));
+    }
+
+    #[test]
+    fn test_clean_target_dir() {
+        let target = TempDir::new().autorm();
+        let out = target.path().join("debug/build/pkg-0123/out");
+        let keep = out.join("fixtures");
+        let leftover = out.join(format!("test-{}", Uuid::new_v4()));
+        let trash = target.path().join(format!("tmp/.trash-{}", Uuid::new_v4()));
+        fs::create_dir_all(&keep).unwrap();
+        fs::create_dir_all(leftover.join("a")).unwrap();
+        fs::write(leftover.join("a/data.bin"), [0u8; 100]).unwrap();
+        fs::create_dir_all(&trash).unwrap();
+
+        let prefixes = ["test-".to_string()];
+        let roots = search_roots(target.path());
+        assert_eq!(roots, [out.clone(), target.path().join("tmp")]);
+        let found: Vec<_> = find_leftovers(&roots, &prefixes)
+            .iter()
+            .map(Leftover::path)
+            .collect();
+        assert_eq!(found, [leftover.clone(), trash.clone()]);
+
+        let run_clean = |older_than, dry_run| {
+            let mut out = Vec::new();
+            let command = Command::Clean {
+                older_than,
+                dry_run,
+            };
+            let ok = run(command, &roots, &prefixes, &mut out).unwrap();
+            assert!(ok);
+            String::from_utf8(out).unwrap()
+        };
+
+        let output = run_clean(Duration::ZERO, true);
+        assert!(output.contains(&format!("would remove {}", leftover.display())));
+        assert!(leftover.is_dir());
+
+        run_clean(DEFAULT_OLDER_THAN, false);
+        assert!(leftover.is_dir());
+
+        run_clean(Duration::ZERO, false);
+        assert!(!leftover.try_exists().unwrap());
+        assert!(!trash.try_exists().unwrap());
+        assert!(keep.is_dir());
+    }
+
+    #[test]
+    fn test_du_reports_unknown_size() {
+        let root = TempDir::new().autorm();
+        let measured = Leftover {
+            root: root.path().to_path_buf(),
+            name: format!("test-{}", Uuid::new_v4()).into(),
+            age: None,
+        };
+        fs::create_dir(measured.path()).unwrap();
+        // Removed after it was found, for example by a concurrent `clean`.
+        let missing = Leftover {
+            root: root.path().to_path_buf(),
+            name: format!("test-{}", Uuid::new_v4()).into(),
+            age: None,
+        };
+
+        let mut out = Vec::new();
+        assert!(!du(&[measured, missing], &mut out).unwrap());
+        let output = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = output.lines().map(str::trim_start).collect();
+        assert!(lines[0].starts_with(|c: char| c.is_ascii_digit()));
+        assert!(lines[1].starts_with("?  "));
+        assert!(lines[2].ends_with("  total"));
+    }
+
+    #[test]
+    fn test_clean_skips_locked_dir() {
+        let root = TempDir::new().autorm();
+        let name = format!("test-{}", Uuid::new_v4());
+        let leftover = root.path().join(&name);
+        fs::create_dir(&leftover).unwrap();
+
+        let lock_path = root.path().join(format!("{name}.lock"));
+        let lock = DirLock::acquire(&lock_path, crate::LockMode::Shared).unwrap();
+
+        let roots = [root.path().to_path_buf()];
+        let prefixes = ["test-".to_string()];
+        let clean = || {
+            let mut out = Vec::new();
+            let command = Command::Clean {
+                older_than: Duration::ZERO,
+                dry_run: false,
+            };
+            assert!(run(command, &roots, &prefixes, &mut out).unwrap());
+            String::from_utf8(out).unwrap()
+        };
+
+        let output = clean();
+        assert!(output.contains(&format!("skipped {} (in use)", leftover.display())));
+        assert!(leftover.is_dir());
+
+        drop(lock);
+        clean();
+        assert!(!leftover.try_exists().unwrap());
+    }
+}
@@ -0,0 +1,62 @@
+use crate::{create_private_dir, Result};
+use std::ffi::OsStr;
+use std::path::Path;
+use std::process::Command;
+
+/// Name of the private subdirectory used as the spawned program's temporary directory.
+pub(crate) const COMMAND_TMP_DIR: &str = ".tmp";
+
+/// Create a command that runs inside `dir` with its temporary directory under `dir`.
+pub(crate) fn command_in<S: AsRef<OsStr>>(dir: &Path, program: S) -> Result<Command> {
+    let tmp = dir.join(COMMAND_TMP_DIR);
+    create_private_dir(&tmp)?;
+
+    let mut command = Command::new(program);
+    command
+        .current_dir(dir)
+        .env("TMPDIR", &tmp)
+        .env("TEMP", &tmp)
+        .env("TMP", &tmp);
+
+    Ok(command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::COMMAND_TMP_DIR;
+    use crate::TempDir;
+    use std::ffi::OsStr;
+
+    #[test]
+    fn test_command_is_confined_to_temp_dir() {
+        let temp = TempDir::new().autorm();
+        let command = temp.command("true");
+        let tmp = temp.path().join(COMMAND_TMP_DIR);
+
+        assert_eq!(command.get_current_dir(), Some(temp.path()));
+        assert!(tmp.is_dir());
+        for name in ["TMPDIR", "TEMP", "TMP"] {
+            assert!(command
+                .get_envs()
+                .any(|(key, value)| key == name && value == Some(tmp.as_os_str())));
+        }
+        assert_eq!(command.get_program(), OsStr::new("true"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_command_tmp_dir_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = TempDir::new().autorm();
+        temp.command("true");
+        let mode = temp
+            .path()
+            .join(COMMAND_TMP_DIR)
+            .metadata()
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o700);
+    }
+}
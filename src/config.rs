@@ -0,0 +1,166 @@
+use crate::{Error, Result, TempDirBuilder, TempDirConfig};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name of the configuration file read by [`TempDirBuilder::from_config`].
+pub const CONFIG_FILE: &str = "outdir-tempdir.toml";
+
+impl TempDirBuilder {
+    /// Create a builder from the nearest `outdir-tempdir.toml`.
+    ///
+    /// Requires the `config` feature, which enables the `serde` feature.
+    ///
+    /// The file is searched for in runtime `CARGO_MANIFEST_DIR` and its
+    /// ancestors, so a single file at the workspace root applies to every
+    /// member. If `CARGO_MANIFEST_DIR` is not set, the search starts from the
+    /// current directory. If no file is found, the builder tries
+    /// `CARGO_TARGET_TMPDIR` and then `OUT_DIR`.
+    ///
+    /// ```toml
+    /// roots = ["env:TMPDIR", "cargo-target-tmpdir", "out-dir"]
+    /// prefix = "test-"
+    /// mode = 0o700
+    /// cleanup = "keep-on-failure"
+    /// ```
+    ///
+    /// * `roots` lists root candidates in fallback order, using the grammar
+    ///   of [`ROOTS_ENV`](crate::ROOTS_ENV).
+    /// * `prefix` sets [`TempDirBuilder::prefix`].
+    /// * `mode` sets `TempDirBuilder::mode`, usually written as an octal
+    ///   integer. It is ignored on platforms other than Unix. As with the
+    ///   builder, a mode above `0o7777` makes the build fail.
+    /// * `cleanup` sets [`TempDirBuilder::cleanup`]: `keep`, `remove` or
+    ///   `keep-on-failure`.
+    ///
+    /// The keys are the fields of [`TempDirConfig`], and every key is
+    /// optional.
+    ///
+    /// # Errors
+    ///
+    /// If the file cannot be read, it will lead to an `Io` error.
+    /// If it is not valid TOML, or contains unknown keys or values, it will
+    /// lead to an `InvalidConfig` error.
+    pub fn from_config() -> Result<Self> {
+        let start = match std::env::var_os("CARGO_MANIFEST_DIR") {
+            Some(dir) => PathBuf::from(dir),
+            None => std::env::current_dir()?,
+        };
+
+        match find_config(&start) {
+            Some(path) => Self::from_config_file(path),
+            None => Ok(TempDirConfig::default().into()),
+        }
+    }
+
+    /// Create a builder from the specified configuration file.
+    ///
+    /// Requires the `config` feature. See [`TempDirBuilder::from_config`] for
+    /// the file format.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`TempDirBuilder::from_config`].
+    pub fn from_config_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        parse(&text).map_err(|message| Error::InvalidConfig {
+            path: path.to_path_buf(),
+            message,
+        })
+    }
+}
+
+fn find_config(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .map(|dir| dir.join(CONFIG_FILE))
+        .find(|path| path.is_file())
+}
+
+fn parse(text: &str) -> std::result::Result<TempDirBuilder, String> {
+    let config: TempDirConfig =
+        toml::from_str(text).map_err(|error| error.message().to_string())?;
+    Ok(config.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TempDir;
+
+    #[test]
+    fn test_from_config_file() {
+        let dir = TempDir::new().autorm();
+        let root = TempDir::new().autorm();
+
+        let path = dir.path().join(CONFIG_FILE);
+        fs::write(
+            &path,
+            format!(
+                "roots = [{:?}, \"out-dir\"]\n\
+             prefix = \"ci-\"\n\
+             mode = 0o750\n\
+             cleanup = \"remove\"\n",
+                format!("path:{}", root.path().display())
+            ),
+        )
+        .unwrap();
+
+        let created = TempDirBuilder::from_config_file(&path)
+            .unwrap()
+            .build()
+            .unwrap();
+        let created_path = created.path().to_path_buf();
+        assert_eq!(
+            created_path.parent(),
+            Some(root.path().canonicalize().unwrap().as_path())
+        );
+        assert!(created_path
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .starts_with("ci-"));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = created_path.metadata().unwrap().permissions().mode();
+            assert_eq!(mode & 0o7777, 0o750);
+        }
+
+        drop(created);
+        assert!(!created_path.try_exists().unwrap());
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        for text in [
+            "roots = [\"nowhere\"]",
+            "roots = \"out-dir\"",
+            "mode = \"750\"",
+            "cleanup = \"sometimes\"",
+            "root = [\"out-dir\"]",
+            "roots = [",
+        ] {
+            assert!(parse(text).is_err(), "{text}");
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_config_rejects_invalid_mode() {
+        let builder = parse("roots = [\"out-dir\"]\nmode = 0o17777").unwrap();
+        assert!(matches!(builder.build(), Err(Error::InvalidMode(0o17777))));
+    }
+
+    #[test]
+    fn test_find_config_walks_up() {
+        let dir = TempDir::new().autorm();
+        let nested = dir.path().join("crates/member");
+        fs::create_dir_all(&nested).unwrap();
+        assert_eq!(find_config(&nested), None);
+
+        fs::write(dir.path().join(CONFIG_FILE), "").unwrap();
+        assert_eq!(find_config(&nested), Some(dir.path().join(CONFIG_FILE)));
+    }
+}
@@ -0,0 +1,98 @@
+use crate::{Error, Result};
+use std::cell::Cell;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Serialises changes of the process-wide current directory.
+static CURRENT_DIR_LOCK: Mutex<()> = Mutex::new(());
+
+thread_local! {
+    /// Number of guards alive on this thread. `CURRENT_DIR_LOCK` is not
+    /// reentrant, so a second guard on the same thread is refused.
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Guard that keeps the process current directory inside a [`TempDir`](crate::TempDir).
+///
+/// Created by [`TempDir::enter`](crate::TempDir::enter). The previous current
+/// directory is restored when this value is dropped.
+///
+/// The current directory is process-wide, so only one guard can exist at a
+/// time. Other threads calling [`TempDir::enter`](crate::TempDir::enter) block
+/// until this guard is dropped, and calls on the same thread fail.
+pub struct CurrentDirGuard<'a> {
+    previous: PathBuf,
+    entered: &'a AtomicUsize,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl<'a> CurrentDirGuard<'a> {
+    pub(crate) fn enter(path: &Path, entered: &'a AtomicUsize) -> Result<Self> {
+        if DEPTH.get() != 0 {
+            return Err(Error::CurrentDirAlreadyEntered);
+        }
+
+        // A test that panicked while holding a guard has already restored the
+        // previous directory in `Drop`, so a poisoned lock is safe to reuse.
+        let lock = CURRENT_DIR_LOCK
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let previous = env::current_dir()?;
+        env::set_current_dir(path)?;
+        entered.fetch_add(1, Ordering::SeqCst);
+        DEPTH.set(DEPTH.get() + 1);
+
+        Ok(Self {
+            previous,
+            entered,
+            _lock: lock,
+        })
+    }
+
+    /// Get the current directory that is restored when this guard is dropped.
+    pub fn previous(&self) -> &Path {
+        &self.previous
+    }
+}
+
+impl Drop for CurrentDirGuard<'_> {
+    /// Restore the previous current directory.
+    fn drop(&mut self) {
+        let result = env::set_current_dir(&self.previous);
+        self.entered.fetch_sub(1, Ordering::SeqCst);
+        DEPTH.set(DEPTH.get() - 1);
+
+        if let Err(error) = result {
+            if !std::thread::panicking() {
+                panic!(
+                    "failed to restore current directory \"{}\": {error}",
+                    self.previous.display()
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::TempDir;
+    use std::sync::atomic::Ordering;
+
+    // Tests that change the current directory live in `tests/cwd.rs`, so that
+    // they run in their own process.
+
+    #[test]
+    fn test_drop_skips_removal_while_entered() {
+        let rmdir = {
+            let temp = TempDir::new().autorm();
+            // Simulate a leaked guard without holding the process-wide lock.
+            temp.entered.fetch_add(1, Ordering::SeqCst);
+            temp.path().to_path_buf()
+        };
+
+        assert!(rmdir.try_exists().unwrap());
+        std::fs::remove_dir_all(&rmdir).unwrap();
+    }
+}
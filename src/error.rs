@@ -14,6 +14,31 @@ pub enum Error {
     OutDirNotFound,
     CargoTargetTmpDirNotFound,
     InvalidPath(PathBuf),
+    SymlinkInRemovalPath(PathBuf),
+    MountPointInRemovalPath(PathBuf),
+    RemovalFailed {
+        attempts: u32,
+        error: io::Error,
+    },
+    QuotaExceeded {
+        limit: u64,
+        used: u64,
+        largest: Vec<(PathBuf, u64)>,
+    },
+    RootNotAbsolute(PathBuf),
+    RootIsFilesystemRoot(PathBuf),
+    RootIsHomeDir(PathBuf),
+    RootWorldWritable(PathBuf),
+    InvalidConfig {
+        path: PathBuf,
+        message: String,
+    },
+    InvalidRootSpec(String),
+    ExportNotFound,
+    InvalidExport(String),
+    NotOwner(PathBuf),
+    CurrentDirAlreadyEntered,
+    InvalidMode(u32),
 }
 
 /// A specialized [`Result`] type for outdir-tempdir.
@@ -56,6 +81,91 @@ impl fmt::Display for Error {
             OutDirNotFound => write!(formatter, "OUT_DIR not found"),
             CargoTargetTmpDirNotFound => write!(formatter, "CARGO_TARGET_TMPDIR not found"),
             InvalidPath(p) => write!(formatter, "Invalid path {}", p.display()),
+            SymlinkInRemovalPath(p) => {
+                write!(
+                    formatter,
+                    "\"{}\" is a symbolic link, refusing to remove through it",
+                    p.display()
+                )
+            }
+            RemovalFailed { attempts, error } => {
+                write!(
+                    formatter,
+                    "failed to remove the temporary directory after {attempts} attempt(s): {error}"
+                )
+            }
+            QuotaExceeded {
+                limit,
+                used,
+                largest,
+            } => {
+                write!(
+                    formatter,
+                    "temporary directory uses {used} bytes, exceeding the quota of {limit} bytes"
+                )?;
+                for (index, (path, size)) in largest.iter().enumerate() {
+                    let separator = if index == 0 {
+                        "; largest files: "
+                    } else {
+                        ", "
+                    };
+                    write!(formatter, "{separator}{} ({size} bytes)", path.display())?;
+                }
+                Ok(())
+            }
+            RootNotAbsolute(p) => {
+                write!(
+                    formatter,
+                    "root \"{}\" is not an absolute path",
+                    p.display()
+                )
+            }
+            RootIsFilesystemRoot(p) => {
+                write!(formatter, "root \"{}\" is the filesystem root", p.display())
+            }
+            RootIsHomeDir(p) => write!(formatter, "root \"{}\" is the home directory", p.display()),
+            RootWorldWritable(p) => {
+                write!(
+                    formatter,
+                    "root \"{}\" is world-writable without the sticky bit",
+                    p.display()
+                )
+            }
+            InvalidConfig { path, message } => {
+                write!(
+                    formatter,
+                    "invalid config \"{}\": {message}",
+                    path.display()
+                )
+            }
+            InvalidRootSpec(spec) => write!(formatter, "invalid root candidate \"{spec}\""),
+            ExportNotFound => write!(
+                formatter,
+                "no temporary directory was exported to this process"
+            ),
+            InvalidExport(message) => {
+                write!(formatter, "invalid exported temporary directory: {message}")
+            }
+            NotOwner(p) => {
+                write!(formatter, "\"{}\" is owned by another process", p.display())
+            }
+            CurrentDirAlreadyEntered => write!(
+                formatter,
+                "the current directory is already entered on this thread"
+            ),
+            InvalidMode(mode) => {
+                write!(
+                    formatter,
+                    "invalid mode 0o{mode:o}, expected at most 0o7777"
+                )
+            }
+            MountPointInRemovalPath(p) => {
+                write!(
+                    formatter,
+                    "\"{}\" is on a different file system, refusing to remove across it",
+                    p.display()
+                )
+            }
         }
     }
 }
@@ -63,7 +173,9 @@ impl fmt::Display for Error {
 impl std::error::Error for Error {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
-            Error::Io(error) | Error::RootCandidatesExhausted(error) => Some(error),
+            Error::Io(error)
+            | Error::RootCandidatesExhausted(error)
+            | Error::RemovalFailed { error, .. } => Some(error),
             _ => None,
         }
     }
@@ -0,0 +1,381 @@
+use crate::Error;
+use std::fmt;
+use std::path::Path;
+use std::sync::{Arc, PoisonError, RwLock};
+
+type Handler = dyn Fn(&Event<'_>) + Send + Sync;
+
+static HANDLER: RwLock<Option<Arc<Handler>>> = RwLock::new(None);
+
+/// Serialises tests that install a handler, since there is only one per process.
+#[cfg(test)]
+pub(crate) static HANDLER_TEST_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+/// Lifecycle event of a temporary directory, passed to the handler installed
+/// with [`set_event_handler`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Event<'a> {
+    /// A root candidate was skipped because it is not available, for example
+    /// because its environment variable is not set.
+    ///
+    /// `candidate` uses the grammar of [`ROOTS_ENV`](crate::ROOTS_ENV).
+    RootSkipped { candidate: &'a str },
+
+    /// A root candidate could not be validated or used.
+    RootFailed {
+        candidate: &'a str,
+        root: &'a Path,
+        error: &'a Error,
+    },
+
+    /// A temporary directory was created.
+    Created { path: &'a Path, root: &'a Path },
+
+    /// A temporary directory was renamed for removal in the background. See
+    /// [`TempDir::autorm_deferred`](crate::TempDir::autorm_deferred).
+    /// [`Event::Removed`] or [`Event::RemovalFailed`] follows once it is gone.
+    ///
+    /// As with [`Event::Removed`], `path` is the directory that is removed.
+    Deferred { path: &'a Path },
+
+    /// A temporary directory was removed.
+    ///
+    /// `path` is the directory that was actually removed, that is, the
+    /// top-level directory under the root, such as `root/test-<uuid>`, rather
+    /// than the nested path returned by [`TempDir::path`](crate::TempDir::path).
+    Removed { path: &'a Path },
+
+    /// A temporary directory could not be removed. `path` is the same as in
+    /// [`Event::Removed`].
+    RemovalFailed { path: &'a Path, error: &'a Error },
+
+    /// A temporary directory was dropped without being removed.
+    Kept { path: &'a Path },
+}
+
+impl fmt::Display for Event<'_> {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Event::RootSkipped { candidate } => {
+                write!(
+                    formatter,
+                    "skipped root candidate {candidate}: not set or empty"
+                )
+            }
+            Event::RootFailed {
+                candidate,
+                root,
+                error,
+            } => write!(
+                formatter,
+                "root candidate {candidate} (\"{}\") failed: {error}",
+                root.display()
+            ),
+            Event::Created { path, root } => write!(
+                formatter,
+                "created \"{}\" in root \"{}\"",
+                path.display(),
+                root.display()
+            ),
+            Event::Deferred { path } => {
+                write!(
+                    formatter,
+                    "scheduled \"{}\" for removal in the background",
+                    path.display()
+                )
+            }
+            Event::Removed { path } => write!(formatter, "removed \"{}\"", path.display()),
+            Event::RemovalFailed { path, error } => {
+                write!(
+                    formatter,
+                    "failed to remove \"{}\": {error}",
+                    path.display()
+                )
+            }
+            Event::Kept { path } => write!(formatter, "kept \"{}\"", path.display()),
+        }
+    }
+}
+
+/// Install a process-wide handler for temporary directory lifecycle events.
+///
+/// The handler replaces any previously installed one. It is called
+/// synchronously on the thread that creates or drops the directory, including
+/// the background threads used by
+/// [`TempDir::autorm_deferred`](crate::TempDir::autorm_deferred) and the
+/// `tokio` feature. Since events are also emitted while a panicking test is
+/// unwinding, the handler must not panic.
+pub fn set_event_handler<F>(handler: F)
+where
+    F: Fn(&Event<'_>) + Send + Sync + 'static,
+{
+    *HANDLER.write().unwrap_or_else(PoisonError::into_inner) = Some(Arc::new(handler));
+}
+
+/// Remove the handler installed with [`set_event_handler`].
+pub fn clear_event_handler() {
+    *HANDLER.write().unwrap_or_else(PoisonError::into_inner) = None;
+}
+
+/// Pass `event` to the installed handler, and to `log` and `tracing` at debug
+/// level when those features are enabled.
+pub(crate) fn emit(event: Event<'_>) {
+    #[cfg(feature = "log")]
+    log::debug!(target: "outdir_tempdir", "{event}");
+    #[cfg(feature = "tracing")]
+    tracing::debug!(target: "outdir_tempdir", "{event}");
+
+    // Call the handler without holding the lock, so that it may replace itself.
+    let handler = HANDLER
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone();
+    if let Some(handler) = handler {
+        handler(&event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TempDir;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_event_handler() {
+        static EVENTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+        let _lock = HANDLER_TEST_LOCK.lock();
+
+        let parent = TempDir::new().autorm();
+        let root = parent.path().canonicalize().unwrap();
+
+        // Other tests run concurrently, so only events under `root` are recorded.
+        let filter = root.clone();
+        set_event_handler(move |event| {
+            let line = match event {
+                Event::RootSkipped { candidate }
+                    if *candidate == "env:OUTDIR_TEMPDIR_TEST_EVENT_UNSET" =>
+                {
+                    "skipped".to_string()
+                }
+                Event::Created { path, root } if *root == filter => {
+                    format!("created {}", path.file_name().unwrap().to_string_lossy())
+                }
+                // The top-level directory is reported, not the nested path.
+                Event::Removed { path } if path.parent() == Some(filter.as_path()) => {
+                    "removed".to_string()
+                }
+                Event::Kept { path } if path.starts_with(&filter) => "kept".to_string(),
+                _ => return,
+            };
+            EVENTS.lock().unwrap().push(line);
+        });
+
+        let builder = || {
+            TempDir::builder()
+                .env("OUTDIR_TEMPDIR_TEST_EVENT_UNSET")
+                .path(&root)
+        };
+        drop(builder().build_with_path("removed").unwrap().autorm());
+        drop(builder().build_with_path("kept").unwrap());
+        clear_event_handler();
+
+        assert_eq!(
+            *EVENTS.lock().unwrap(),
+            [
+                "skipped",
+                "created removed",
+                "removed",
+                "skipped",
+                "created kept",
+                "kept"
+            ]
+        );
+
+        for entry in std::fs::read_dir(&root).unwrap() {
+            std::fs::remove_dir_all(entry.unwrap().path()).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_close_events() {
+        static EVENTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+        let _lock = HANDLER_TEST_LOCK.lock();
+
+        let parent = TempDir::new().autorm();
+        let root = parent.path().canonicalize().unwrap();
+
+        let filter = root.clone();
+        set_event_handler(move |event| {
+            let name = match event {
+                Event::Created { root, .. } if *root == filter => "created",
+                Event::Deferred { path } if path.starts_with(&filter) => "deferred",
+                Event::Removed { path } if path.starts_with(&filter) => "removed",
+                Event::RemovalFailed { path, .. } if path.starts_with(&filter) => "failed",
+                Event::Kept { path } if path.starts_with(&filter) => "kept",
+                _ => return,
+            };
+            EVENTS.lock().unwrap().push(name.to_string());
+        });
+
+        let closed = TempDir::builder().path(&root).build().unwrap();
+        let result = closed.close();
+        clear_event_handler();
+
+        result.unwrap();
+        assert_eq!(*EVENTS.lock().unwrap(), ["created", "removed"]);
+    }
+
+    #[test]
+    fn test_event_display() {
+        let path = Path::new("/tmp/test-a");
+        assert_eq!(
+            Event::RootSkipped {
+                candidate: "env:TMPDIR"
+            }
+            .to_string(),
+            "skipped root candidate env:TMPDIR: not set or empty"
+        );
+        assert_eq!(
+            Event::Created {
+                path,
+                root: Path::new("/tmp")
+            }
+            .to_string(),
+            "created \"/tmp/test-a\" in root \"/tmp\""
+        );
+        assert_eq!(
+            Event::RemovalFailed {
+                path,
+                error: &Error::SymlinkInRemovalPath(path.to_path_buf())
+            }
+            .to_string(),
+            "failed to remove \"/tmp/test-a\": \"/tmp/test-a\" is a symbolic link, refusing to remove through it"
+        );
+    }
+
+    #[cfg(feature = "log")]
+    #[test]
+    fn test_log_records() {
+        struct Logger;
+        static RECORDS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+        impl log::Log for Logger {
+            fn enabled(&self, metadata: &log::Metadata) -> bool {
+                metadata.target() == "outdir_tempdir"
+            }
+
+            fn log(&self, record: &log::Record) {
+                if self.enabled(record.metadata()) && record.level() == log::Level::Debug {
+                    RECORDS.lock().unwrap().push(record.args().to_string());
+                }
+            }
+
+            fn flush(&self) {}
+        }
+
+        log::set_logger(&Logger).unwrap();
+        log::set_max_level(log::LevelFilter::Debug);
+
+        let temp = TempDir::builder()
+            .env("OUTDIR_TEMPDIR_TEST_LOG_UNSET")
+            .out_dir()
+            .build()
+            .unwrap()
+            .autorm();
+        let path = temp.path().to_path_buf();
+        drop(temp);
+
+        let records = RECORDS.lock().unwrap();
+        assert!(records.contains(
+            &"skipped root candidate env:OUTDIR_TEMPDIR_TEST_LOG_UNSET: not set or empty"
+                .to_string()
+        ));
+        assert!(records.contains(&format!("removed \"{}\"", path.display())));
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn test_tracing_events() {
+        use tracing::field::{Field, Visit};
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Level, Metadata};
+
+        #[derive(Default)]
+        struct Subscriber {
+            events: Mutex<Vec<(String, Level, String)>>,
+        }
+
+        struct Message(String);
+
+        impl Visit for Message {
+            fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+                if field.name() == "message" {
+                    self.0 = format!("{value:?}");
+                }
+            }
+        }
+
+        impl tracing::Subscriber for Subscriber {
+            fn enabled(&self, _: &Metadata<'_>) -> bool {
+                true
+            }
+
+            fn new_span(&self, _: &Attributes<'_>) -> Id {
+                Id::from_u64(1)
+            }
+
+            fn record(&self, _: &Id, _: &Record<'_>) {}
+
+            fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+            fn event(&self, event: &Event<'_>) {
+                let mut message = Message(String::new());
+                event.record(&mut message);
+                let metadata = event.metadata();
+                self.events.lock().unwrap().push((
+                    metadata.target().to_string(),
+                    *metadata.level(),
+                    message.0,
+                ));
+            }
+
+            fn enter(&self, _: &Id) {}
+
+            fn exit(&self, _: &Id) {}
+        }
+
+        let parent = TempDir::new().autorm();
+        let root = parent.path().canonicalize().unwrap();
+        let subscriber = std::sync::Arc::new(Subscriber::default());
+
+        // The subscriber is only installed on this thread, so events from
+        // concurrently running tests are not recorded.
+        let path = tracing::subscriber::with_default(subscriber.clone(), || {
+            let temp = TempDir::builder()
+                .env("OUTDIR_TEMPDIR_TEST_TRACING_UNSET")
+                .path(&root)
+                .build()
+                .unwrap()
+                .autorm();
+            temp.path().to_path_buf()
+        });
+
+        let events = subscriber.events.lock().unwrap();
+        let expected = [
+            "skipped root candidate env:OUTDIR_TEMPDIR_TEST_TRACING_UNSET: not set or empty"
+                .to_string(),
+            format!(
+                "created \"{}\" in root \"{}\"",
+                path.display(),
+                root.display()
+            ),
+            format!("removed \"{}\"", path.display()),
+        ];
+        assert_eq!(
+            *events,
+            expected.map(|message| ("outdir_tempdir".to_string(), Level::DEBUG, message))
+        );
+    }
+}
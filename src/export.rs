@@ -0,0 +1,239 @@
+use crate::builder::Cleanup;
+use crate::{report, Error, Result, TempDir, TempDirRoot};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Environment variable holding the exported directory path.
+pub const EXPORT_PATH_ENV: &str = "OUTDIR_TEMPDIR_EXPORT_PATH";
+
+/// Environment variable holding the root of the exported directory.
+const EXPORT_ROOT_ENV: &str = "OUTDIR_TEMPDIR_EXPORT_ROOT";
+
+/// Environment variable holding the removal target, relative to the root.
+const EXPORT_REMOVE_ENV: &str = "OUTDIR_TEMPDIR_EXPORT_REMOVE";
+
+/// Environment variable holding the cleanup policy of the child, set only
+/// when ownership is transferred.
+const EXPORT_CLEANUP_ENV: &str = "OUTDIR_TEMPDIR_EXPORT_CLEANUP";
+
+impl TempDir {
+    /// Pass this directory to a child process through environment variables.
+    ///
+    /// This process keeps ownership: the child reattaches with
+    /// [`TempDir::from_env`] and never removes the directory, while this
+    /// value removes it as usual when dropped. Use
+    /// [`TempDir::transfer_env`] to let the child remove it instead.
+    pub fn export_env(&self, command: &mut Command) {
+        command
+            .env(EXPORT_PATH_ENV, &self.full)
+            .env(EXPORT_ROOT_ENV, &self.root)
+            .env(EXPORT_REMOVE_ENV, &self.remove_target_rel)
+            .env_remove(EXPORT_CLEANUP_ENV);
+    }
+
+    /// Pass this directory and the responsibility for removing it to a child process.
+    ///
+    /// This value is consumed without removing the directory. The child
+    /// reattaches with [`TempDir::from_env`] and gets the current cleanup
+    /// policy: removal with [`TempDir::autorm`] or
+    /// [`TempDir::keep_on_failure`], or none. Quotas, removal retries and
+    /// deferred removal are not transferred.
+    pub fn transfer_env(mut self, command: &mut Command) {
+        self.export_env(command);
+        let cleanup = match (self.autorm, self.keep_on_failure) {
+            (false, _) => "keep",
+            (true, false) => "remove",
+            (true, true) => "keep-on-failure",
+        };
+        command.env(EXPORT_CLEANUP_ENV, cleanup);
+        self.owned = false;
+    }
+
+    /// Reattach to a directory passed by the parent process with
+    /// [`TempDir::export_env`] or [`TempDir::transfer_env`].
+    ///
+    /// After [`TempDir::export_env`], the returned value does not own the
+    /// directory: it is never removed when the value is dropped, even if
+    /// [`TempDir::autorm`] is called, and [`TempDir::close`] fails with a
+    /// `NotOwner` error. After [`TempDir::transfer_env`], the returned value
+    /// owns the directory and removes it according to the parent's cleanup
+    /// policy.
+    ///
+    /// # Errors
+    ///
+    /// If no directory was exported, it will lead to an `ExportNotFound` error.
+    /// If the exported variables are inconsistent, the root is not safe as
+    /// described in [`TempDirBuilder`](crate::TempDirBuilder), or the directory
+    /// does not exist, it will lead to an `InvalidExport` error.
+    pub fn from_env() -> Result<Self> {
+        Self::from_vars(|name| std::env::var_os(name))
+    }
+
+    fn from_vars<F: Fn(&str) -> Option<OsString>>(var: F) -> Result<Self> {
+        let Some(full) = var(EXPORT_PATH_ENV).map(PathBuf::from) else {
+            return Err(Error::ExportNotFound);
+        };
+        let invalid = |message: &str| Error::InvalidExport(message.to_string());
+
+        let root = var(EXPORT_ROOT_ENV)
+            .map(PathBuf::from)
+            .ok_or_else(|| invalid("the root is missing"))?;
+        let remove_target_rel = var(EXPORT_REMOVE_ENV)
+            .map(PathBuf::from)
+            .ok_or_else(|| invalid("the removal target is missing"))?;
+
+        // The child may remove `root/remove_target_rel`, so apply the same
+        // checks as to a newly created directory: a safe root, and relative
+        // paths below it that cannot escape it.
+        let relative = |path: &Path| {
+            TempDir::cleanse_relative_path(path)
+                .ok()
+                .filter(|cleansed| !cleansed.as_os_str().is_empty() && cleansed == path)
+                .is_some()
+        };
+        let target = full
+            .strip_prefix(&root)
+            .map_err(|_| invalid("the path is outside the root"))?
+            .to_path_buf();
+        if !relative(&target) {
+            return Err(invalid("the path is not a relative path below the root"));
+        }
+        if !relative(&remove_target_rel) {
+            return Err(invalid("the removal target is not a relative path"));
+        }
+        if !target.starts_with(&remove_target_rel) {
+            return Err(invalid("the path is outside the removal target"));
+        }
+
+        let root = TempDir::validate_root(root, false)
+            .map_err(|error| invalid(&format!("the root is not safe: {error}")))?;
+        let full = root.join(target);
+        if !full.is_dir() {
+            return Err(invalid("the directory does not exist"));
+        }
+
+        let (owned, cleanup) = match var(EXPORT_CLEANUP_ENV) {
+            None => (false, Cleanup::Keep),
+            Some(value) => match value.to_str() {
+                Some("keep") => (true, Cleanup::Keep),
+                Some("remove") => (true, Cleanup::Remove),
+                Some("keep-on-failure") => (true, Cleanup::KeepOnFailure),
+                _ => return Err(invalid("unknown cleanup policy")),
+            },
+        };
+
+        let report_id = report::register(&full, &root);
+        let mut tempdir = Self::from_parts(
+            TempDirRoot::Path(root.clone()),
+            root,
+            remove_target_rel,
+            full,
+            report_id,
+        );
+        tempdir.owned = owned;
+        tempdir.autorm = cleanup != Cleanup::Keep;
+        tempdir.keep_on_failure = cleanup == Cleanup::KeepOnFailure;
+        Ok(tempdir)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Read the variables set on `command` instead of the process environment.
+    fn reattach(command: &Command) -> Result<TempDir> {
+        let vars: HashMap<_, _> = command
+            .get_envs()
+            .filter_map(|(name, value)| Some((name.to_str()?.to_string(), value?.to_os_string())))
+            .collect();
+        TempDir::from_vars(|name| vars.get(name).cloned())
+    }
+
+    #[test]
+    fn test_export_env_keeps_ownership() {
+        let parent = TempDir::builder()
+            .out_dir()
+            .build_with_path("a/b")
+            .unwrap()
+            .autorm();
+        let mut command = Command::new("child");
+        parent.export_env(&mut command);
+
+        let child = reattach(&command).unwrap().autorm();
+        assert_eq!(child.path(), parent.path());
+        drop(child);
+        assert!(parent.path().is_dir());
+
+        let child = reattach(&command).unwrap();
+        assert!(matches!(child.close(), Err(Error::NotOwner(_))));
+        assert!(parent.path().is_dir());
+
+        let private_root = parent.removal_target();
+        drop(parent);
+        assert!(!private_root.try_exists().unwrap());
+    }
+
+    #[test]
+    fn test_transfer_env_moves_ownership() {
+        let parent = TempDir::builder()
+            .out_dir()
+            .build_with_path("a/b")
+            .unwrap()
+            .autorm();
+        let path = parent.path().to_path_buf();
+        let private_root = parent.removal_target();
+        let mut command = Command::new("child");
+        parent.transfer_env(&mut command);
+        assert!(path.is_dir());
+
+        let child = reattach(&command).unwrap();
+        assert_eq!(child.path(), path);
+        assert!(child.info().autorm());
+        drop(child);
+        assert!(!private_root.try_exists().unwrap());
+    }
+
+    #[test]
+    fn test_from_env_rejects_invalid_exports() {
+        assert!(matches!(
+            TempDir::from_vars(|_| None),
+            Err(Error::ExportNotFound)
+        ));
+
+        let parent = TempDir::new().autorm();
+        let root = parent.path().parent().unwrap().as_os_str().to_os_string();
+        let name = parent.path().file_name().unwrap().to_os_string();
+        let escaping = parent.path().join("../..");
+        let cases = [
+            (EXPORT_REMOVE_ENV, "../other".into()),
+            (EXPORT_REMOVE_ENV, "".into()),
+            (EXPORT_REMOVE_ENV, parent.path().as_os_str().to_os_string()),
+            (EXPORT_ROOT_ENV, "relative".into()),
+            (EXPORT_ROOT_ENV, "/".into()),
+            (EXPORT_PATH_ENV, "/elsewhere".into()),
+            (EXPORT_PATH_ENV, escaping.into_os_string()),
+        ];
+
+        for (override_name, override_value) in cases {
+            let result = TempDir::from_vars(|var| {
+                if var == override_name {
+                    return Some(override_value.clone());
+                }
+                match var {
+                    EXPORT_PATH_ENV => Some(parent.path().as_os_str().to_os_string()),
+                    EXPORT_ROOT_ENV => Some(root.clone()),
+                    EXPORT_REMOVE_ENV => Some(name.clone()),
+                    _ => None,
+                }
+            });
+            assert!(
+                matches!(result, Err(Error::InvalidExport(_))),
+                "{override_name}={}",
+                override_value.to_string_lossy()
+            );
+        }
+    }
+}
@@ -0,0 +1,307 @@
+use crate::snapshot::{slash_path, EntryKind};
+use crate::walk::walk;
+use crate::Result;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Environment variable that makes [`TempDir::assert_golden`](crate::TempDir::assert_golden)
+/// overwrite the expected directory instead of failing.
+pub const BLESS_ENV: &str = "OUTDIR_TEMPDIR_BLESS";
+
+/// Differences between an actual directory tree and an expected directory tree.
+///
+/// Paths are relative to the compared directories and use `/` as separator.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GoldenDiff {
+    added: Vec<String>,
+    missing: Vec<String>,
+    differing: Vec<String>,
+}
+
+impl GoldenDiff {
+    pub(crate) fn compare(actual: &Path, expected: &Path) -> Result<Self> {
+        let actual = read_tree(actual)?;
+        let expected = match read_tree(expected) {
+            Ok(tree) => tree,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(error) => return Err(error.into()),
+        };
+
+        let mut diff = Self::default();
+        for (path, node) in &actual {
+            match expected.get(path) {
+                None => diff.added.push(path.clone()),
+                Some(expected_node) if !node.same_as(expected_node)? => {
+                    diff.differing.push(path.clone())
+                }
+                Some(_) => {}
+            }
+        }
+
+        for path in expected.keys() {
+            if !actual.contains_key(path) {
+                diff.missing.push(path.clone());
+            }
+        }
+
+        Ok(diff)
+    }
+
+    /// Returns `true` if the trees are identical.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.missing.is_empty() && self.differing.is_empty()
+    }
+
+    /// Get the paths that exist only in the actual tree.
+    pub fn added(&self) -> &[String] {
+        &self.added
+    }
+
+    /// Get the paths that exist only in the expected tree.
+    pub fn missing(&self) -> &[String] {
+        &self.missing
+    }
+
+    /// Get the paths whose kind, contents or link target differ.
+    pub fn differing(&self) -> &[String] {
+        &self.differing
+    }
+}
+
+impl fmt::Display for GoldenDiff {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        for path in &self.added {
+            writeln!(formatter, "added: {path}")?;
+        }
+        for path in &self.missing {
+            writeln!(formatter, "missing: {path}")?;
+        }
+        for path in &self.differing {
+            writeln!(formatter, "differing: {path}")?;
+        }
+        Ok(())
+    }
+}
+
+enum Node {
+    Dir,
+    File(PathBuf),
+    Symlink(PathBuf),
+    /// Named pipes, sockets and devices are compared by kind only.
+    Special(EntryKind),
+}
+
+impl Node {
+    fn same_as(&self, other: &Node) -> io::Result<bool> {
+        match (self, other) {
+            (Node::Dir, Node::Dir) => Ok(true),
+            (Node::File(a), Node::File(b)) => Ok(fs::read(a)? == fs::read(b)?),
+            (Node::Symlink(a), Node::Symlink(b)) => Ok(a == b),
+            (Node::Special(a), Node::Special(b)) => Ok(a == b),
+            _ => Ok(false),
+        }
+    }
+}
+
+fn read_tree(dir: &Path) -> io::Result<BTreeMap<String, Node>> {
+    let mut tree = BTreeMap::new();
+    for entry in walk(dir)? {
+        let node = match EntryKind::of(entry.metadata.file_type()) {
+            EntryKind::Symlink => Node::Symlink(fs::read_link(&entry.full)?),
+            EntryKind::Dir => Node::Dir,
+            EntryKind::File => Node::File(entry.full),
+            kind => Node::Special(kind),
+        };
+        tree.insert(slash_path(&entry.rel), node);
+    }
+
+    Ok(tree)
+}
+
+/// Returns `true` if [`BLESS_ENV`] is set to a value other than empty or `0`.
+pub(crate) fn bless_requested() -> bool {
+    std::env::var_os(BLESS_ENV).is_some_and(|value| !value.is_empty() && value != "0")
+}
+
+/// Replace the `expected` directory with a copy of the `actual` directory.
+///
+/// Since `expected` is removed first, this refuses to bless a path that is
+/// empty, the current or package directory or one of their ancestors, or that
+/// overlaps the `actual` directory.
+pub(crate) fn bless(actual: &Path, expected: &Path) -> io::Result<()> {
+    check_bless_target(actual, expected)?;
+
+    match fs::remove_dir_all(expected) {
+        Ok(()) => {}
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+        Err(error) => return Err(error),
+    }
+
+    fs::create_dir_all(expected)?;
+    for entry in walk(actual)? {
+        let dest = expected.join(&entry.rel);
+        match EntryKind::of(entry.metadata.file_type()) {
+            EntryKind::Symlink => symlink(&fs::read_link(&entry.full)?, &dest)?,
+            EntryKind::Dir => fs::create_dir(&dest)?,
+            EntryKind::File => {
+                fs::copy(&entry.full, &dest)?;
+            }
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("cannot copy special file {}", entry.full.display()),
+                ))
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn check_bless_target(actual: &Path, expected: &Path) -> io::Result<()> {
+    let refuse = |reason: &str| {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("refusing to bless \"{}\": {reason}", expected.display()),
+        ))
+    };
+
+    if expected.as_os_str().is_empty() {
+        return refuse("the path is empty");
+    }
+
+    let resolved = resolve(expected)?;
+    let protected = [
+        std::env::current_dir().ok(),
+        std::env::var_os("CARGO_MANIFEST_DIR").map(PathBuf::from),
+    ];
+    for dir in protected.into_iter().flatten() {
+        let dir = dir.canonicalize().unwrap_or(dir);
+        if dir.starts_with(&resolved) {
+            return refuse("it is the current or package directory, or one of their ancestors");
+        }
+    }
+
+    let actual = actual.canonicalize()?;
+    if resolved.starts_with(&actual) || actual.starts_with(&resolved) {
+        return refuse("it overlaps the temporary directory");
+    }
+
+    Ok(())
+}
+
+/// Make `path` absolute, resolving symbolic links in its longest existing prefix.
+fn resolve(path: &Path) -> io::Result<PathBuf> {
+    let path = std::path::absolute(path)?;
+    let mut existing = path.as_path();
+    let mut rest = Vec::new();
+
+    loop {
+        match existing.canonicalize() {
+            Ok(resolved) => {
+                return Ok(rest.iter().rev().fold(resolved, |dir, name| dir.join(name)));
+            }
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                let (Some(parent), Some(name)) = (existing.parent(), existing.file_name()) else {
+                    return Err(error);
+                };
+                rest.push(name);
+                existing = parent;
+            }
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(not(unix))]
+fn symlink(target: &Path, _link: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("cannot copy symbolic link to {}", target.display()),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::TempDir;
+    use std::fs;
+    use std::io;
+    use std::path::Path;
+
+    #[test]
+    fn test_compare_with_dir() {
+        let actual = TempDir::new().autorm();
+        let expected = TempDir::new().autorm();
+
+        fs::create_dir(actual.path().join("sub")).unwrap();
+        fs::write(actual.path().join("same.txt"), "same").unwrap();
+        fs::write(actual.path().join("changed.txt"), "new").unwrap();
+        fs::write(actual.path().join("sub/added.txt"), "added").unwrap();
+
+        fs::create_dir(expected.path().join("sub")).unwrap();
+        fs::write(expected.path().join("same.txt"), "same").unwrap();
+        fs::write(expected.path().join("changed.txt"), "old").unwrap();
+        fs::write(expected.path().join("missing.txt"), "missing").unwrap();
+
+        let diff = actual.compare_with_dir(expected.path()).unwrap();
+        assert!(!diff.is_empty());
+        assert_eq!(diff.added(), ["sub/added.txt"]);
+        assert_eq!(diff.missing(), ["missing.txt"]);
+        assert_eq!(diff.differing(), ["changed.txt"]);
+    }
+
+    #[test]
+    fn test_bless_copies_actual_tree() {
+        let actual = TempDir::new().autorm();
+        let expected = TempDir::new().autorm();
+
+        fs::create_dir_all(actual.path().join("a/b")).unwrap();
+        fs::write(actual.path().join("a/b/c.txt"), "c").unwrap();
+        fs::write(expected.path().join("stale.txt"), "stale").unwrap();
+
+        super::bless(actual.path(), expected.path()).unwrap();
+
+        assert!(actual.compare_with_dir(expected.path()).unwrap().is_empty());
+        actual.assert_golden(expected.path());
+    }
+
+    #[test]
+    fn test_bless_refuses_dangerous_targets() {
+        let actual = TempDir::new().autorm();
+        fs::write(actual.path().join("a.txt"), "a").unwrap();
+
+        let manifest_dir = env!("CARGO_MANIFEST_DIR");
+        let manifest_parent = Path::new(manifest_dir).parent().unwrap();
+        let inside = actual.path().join("expected");
+        let targets = [
+            Path::new(""),
+            Path::new("."),
+            Path::new(manifest_dir),
+            manifest_parent,
+            &inside,
+        ];
+
+        // Only check the targets, so that a regression cannot touch the
+        // crate's own sources.
+        for target in targets {
+            let error = super::check_bless_target(actual.path(), target).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput, "{target:?}");
+        }
+
+        // `bless` itself is only called on throwaway directories.
+        let error = super::bless(actual.path(), &inside).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert!(!inside.try_exists().unwrap());
+        let error = super::bless(actual.path(), actual.path()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert!(actual.path().join("a.txt").is_file());
+    }
+}
@@ -0,0 +1,130 @@
+use crate::{create_private_dir, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Isolated home and XDG base directories inside a [`TempDir`](crate::TempDir).
+///
+/// Created by [`TempDir::isolated_home`](crate::TempDir::isolated_home).
+/// Apply it to a [`Command`] so that subprocesses never touch the real user's
+/// home directory.
+#[derive(Debug, Clone)]
+pub struct IsolatedHome {
+    home: PathBuf,
+    config: PathBuf,
+    cache: PathBuf,
+    data: PathBuf,
+    runtime: PathBuf,
+}
+
+impl IsolatedHome {
+    pub(crate) fn create(dir: &Path) -> Result<Self> {
+        let home = Self {
+            home: dir.join("home"),
+            config: dir.join("config"),
+            cache: dir.join("cache"),
+            data: dir.join("data"),
+            runtime: dir.join("runtime"),
+        };
+
+        for path in [&home.home, &home.config, &home.cache, &home.data] {
+            fs::create_dir_all(path)?;
+        }
+        // XDG requires the runtime directory to be accessible only by its owner.
+        create_private_dir(&home.runtime)?;
+
+        Ok(home)
+    }
+
+    /// Get the directory used as `HOME`.
+    pub fn home(&self) -> &Path {
+        &self.home
+    }
+
+    /// Get the directory used as `XDG_CONFIG_HOME`.
+    pub fn config(&self) -> &Path {
+        &self.config
+    }
+
+    /// Get the directory used as `XDG_CACHE_HOME`.
+    pub fn cache(&self) -> &Path {
+        &self.cache
+    }
+
+    /// Get the directory used as `XDG_DATA_HOME`.
+    pub fn data(&self) -> &Path {
+        &self.data
+    }
+
+    /// Get the directory used as `XDG_RUNTIME_DIR`.
+    pub fn runtime(&self) -> &Path {
+        &self.runtime
+    }
+
+    /// Get the environment variables that point at the isolated directories.
+    ///
+    /// On Windows, `USERPROFILE` is also included.
+    pub fn envs(&self) -> BTreeMap<&'static str, &Path> {
+        let mut envs = BTreeMap::new();
+        envs.insert("HOME", self.home());
+        #[cfg(windows)]
+        envs.insert("USERPROFILE", self.home());
+        envs.insert("XDG_CONFIG_HOME", self.config());
+        envs.insert("XDG_CACHE_HOME", self.cache());
+        envs.insert("XDG_DATA_HOME", self.data());
+        envs.insert("XDG_RUNTIME_DIR", self.runtime());
+        envs
+    }
+
+    /// Set the environment variables from [`IsolatedHome::envs`] on a command.
+    pub fn apply<'a>(&self, command: &'a mut Command) -> &'a mut Command {
+        command.envs(self.envs())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::TempDir;
+    use std::process::Command;
+
+    #[test]
+    fn test_isolated_home() {
+        let temp = TempDir::new().autorm();
+        let home = temp.isolated_home().unwrap();
+
+        for path in [
+            home.home(),
+            home.config(),
+            home.cache(),
+            home.data(),
+            home.runtime(),
+        ] {
+            assert!(path.starts_with(temp.path()));
+            assert!(path.is_dir());
+        }
+
+        let envs = home.envs();
+        assert_eq!(envs["HOME"], home.home());
+        assert_eq!(envs["XDG_CONFIG_HOME"], home.config());
+        assert_eq!(envs["XDG_RUNTIME_DIR"], home.runtime());
+
+        let mut command = Command::new("true");
+        home.apply(&mut command);
+        assert!(command
+            .get_envs()
+            .any(|(key, value)| key == "HOME" && value == Some(home.home().as_os_str())));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_isolated_runtime_dir_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = TempDir::new().autorm();
+        let home = temp.isolated_home().unwrap();
+        let mode = home.runtime().metadata().unwrap().permissions().mode();
+
+        assert_eq!(mode & 0o777, 0o700);
+    }
+}
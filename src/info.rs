@@ -0,0 +1,120 @@
+use crate::TempDirRoot;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Metadata describing a temporary directory.
+///
+/// Created by [`TempDir::info`](crate::TempDir::info). With the `serde`
+/// feature, it can be serialized, for example to record it in a JSON report.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TempDirInfo {
+    path: PathBuf,
+    root: TempDirRoot,
+    created_at: SystemTime,
+    autorm: bool,
+}
+
+impl TempDirInfo {
+    pub(crate) fn new(
+        path: PathBuf,
+        root: TempDirRoot,
+        created_at: SystemTime,
+        autorm: bool,
+    ) -> Self {
+        Self {
+            path,
+            root,
+            created_at,
+            autorm,
+        }
+    }
+
+    /// Get the path to the temporary directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get the root candidate the directory was created in.
+    pub fn root(&self) -> &TempDirRoot {
+        &self.root
+    }
+
+    /// Get the time the directory was created.
+    pub fn created_at(&self) -> SystemTime {
+        self.created_at
+    }
+
+    /// Check whether the directory is removed when the [`TempDir`](crate::TempDir) is dropped.
+    pub fn autorm(&self) -> bool {
+        self.autorm
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{TempDir, TempDirRoot};
+
+    #[test]
+    fn test_info() {
+        let temp = TempDir::new();
+        let info = temp.info();
+        assert_eq!(info.path(), temp.path());
+        assert_eq!(info.root(), &TempDirRoot::Out);
+        assert!(!info.autorm());
+        assert!(info.created_at().elapsed().is_ok());
+        temp.close().unwrap();
+
+        let temp = TempDir::builder()
+            .env("OUTDIR_TEMPDIR_TEST_INFO_UNSET")
+            .out_dir()
+            .build()
+            .unwrap()
+            .autorm();
+        assert_eq!(temp.info().root(), &TempDirRoot::Out);
+        assert!(temp.info().autorm());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        use crate::{Cleanup, TempDirBuilder, TempDirConfig};
+
+        let config = TempDir::builder()
+            .env("TMPDIR")
+            .path("/scratch")
+            .out_dir()
+            .prefix("ci-")
+            .cleanup(Cleanup::KeepOnFailure)
+            .to_config();
+        let json = serde_json::to_string(&config).unwrap();
+        assert_eq!(
+            json,
+            r#"{"roots":["env:TMPDIR","path:/scratch","out-dir"],"prefix":"ci-","mode":null,"cleanup":"keep-on-failure"}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<TempDirConfig>(&json).unwrap(),
+            config
+        );
+        assert_eq!(TempDirBuilder::from(config.clone()).to_config(), config);
+
+        let partial: TempDirConfig = serde_json::from_str(r#"{"cleanup":"remove"}"#).unwrap();
+        assert_eq!(partial.roots(), TempDirConfig::default().roots());
+        assert_eq!(partial.cleanup(), Cleanup::Remove);
+        assert!(serde_json::from_str::<TempDirConfig>(r#"{"roots":["tmp"]}"#).is_err());
+        assert!(serde_json::from_str::<TempDirConfig>(r#"{"root":[]}"#).is_err());
+
+        let temp = TempDirBuilder::from(TempDirConfig::default())
+            .build()
+            .unwrap()
+            .autorm();
+        let info = temp.info();
+        let json = serde_json::to_value(&info).unwrap();
+        assert_eq!(json["path"], temp.path().to_str().unwrap());
+        assert_eq!(json["autorm"], true);
+        assert_eq!(
+            serde_json::from_value::<crate::TempDirInfo>(json).unwrap(),
+            info
+        );
+    }
+}
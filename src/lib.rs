@@ -35,7 +35,7 @@
 //! Create a temporary directory under `OUT_DIR` with automatic removal.
 //!
 //! ```no_run
-//! # use outdir_tempdir::*;
+//! # use outdir_tempdir::TempDir;
 //! #[test]
 //! fn test_something() {
 //!     let dir = TempDir::new().autorm();
@@ -53,7 +53,7 @@
 //! Create a temporary directory under `OUT_DIR` without automatic removal.
 //!
 //! ```no_run
-//! # use outdir_tempdir::*;
+//! # use outdir_tempdir::TempDir;
 //! #[test]
 //! fn test_something() {
 //!     let dir = TempDir::new();
@@ -69,7 +69,7 @@
 //! Create a temporary directory under `OUT_DIR` using a specified relative path.
 //!
 //! ```no_run
-//! # use outdir_tempdir::*;
+//! # use outdir_tempdir::TempDir;
 //! #[test]
 //! fn test_something() {
 //!     let dir = TempDir::with_path("foo/bar/baz").autorm();
@@ -89,7 +89,7 @@
 //! Create a temporary directory under `CARGO_TARGET_TMPDIR`.
 //!
 //! ```no_run
-//! # use outdir_tempdir::*;
+//! # use outdir_tempdir::TempDir;
 //! #[test]
 //! fn test_something() {
 //!     let dir = TempDir::new_in_target_tmp().autorm();
@@ -103,7 +103,7 @@
 //! Use `CARGO_TARGET_TMPDIR` with a specified relative path.
 //!
 //! ```no_run
-//! # use outdir_tempdir::*;
+//! # use outdir_tempdir::TempDir;
 //! #[test]
 //! fn test_something() {
 //!     let dir = TempDir::with_path_in_target_tmp("foo/bar/baz").autorm();
@@ -117,7 +117,7 @@
 //! Create a temporary directory with caller-defined fallback order.
 //!
 //! ```no_run
-//! # use outdir_tempdir::*;
+//! # use outdir_tempdir::TempDir;
 //! #[test]
 //! fn test_something() {
 //!     let dir = TempDir::builder()
@@ -152,6 +152,11 @@
 //! - [`TempDirBuilder::out_dir`] uses the crate's compile-time `OUT_DIR`.
 //! - Builder-created directories always live under a random private top-level
 //!   directory such as `test-<uuid>`.
+//! - On Unix, the private top-level directory is owner-only (`0o700`).
+//!   `TempDirBuilder::mode` applies a mode to every created level.
+//! - Unavailable candidates and candidates whose directory cannot be created
+//!   fall back to the next one. Unsafe candidates, such as a relative path or
+//!   the filesystem root, stop the build with an error; see [`TempDirBuilder`].
 //!
 //! This is useful in sandboxed environments where `TMPDIR` may point to the
 //! only writable temporary directory, while `OUT_DIR` should remain available as
@@ -171,17 +176,122 @@
 //! Parent-directory components such as `..` and absolute paths are rejected to
 //! avoid escaping from `OUT_DIR` or `CARGO_TARGET_TMPDIR`.
 
+#[cfg(feature = "tokio")]
+mod async_api;
 mod builder;
+#[cfg(feature = "cli")]
+mod cli;
+mod command;
+#[cfg(feature = "config")]
+mod config;
+mod cwd;
 mod error;
-pub use crate::builder::TempDirBuilder;
+mod event;
+mod export;
+mod golden;
+mod home;
+mod info;
+mod lock;
+mod quota;
+mod remove;
+mod report;
+#[cfg(feature = "cli")]
+mod run;
+mod snapshot;
+mod trash;
+mod usage;
+mod walk;
+pub use crate::builder::{Cleanup, TempDirBuilder, TempDirConfig, ROOTS_ENV, RUN_DIR_ENV};
+#[cfg(feature = "cli")]
+#[doc(hidden)]
+pub use crate::cli::main as __cli_main;
+#[cfg(feature = "config")]
+pub use crate::config::CONFIG_FILE;
+pub use crate::cwd::CurrentDirGuard;
 pub use crate::error::{Error, Result};
+pub use crate::event::{clear_event_handler, set_event_handler, Event};
+pub use crate::export::EXPORT_PATH_ENV;
+pub use crate::golden::{GoldenDiff, BLESS_ENV};
+pub use crate::home::IsolatedHome;
+pub use crate::info::TempDirInfo;
+pub use crate::lock::LockMode;
+pub use crate::report::{write_cleanup_report, REPORT_ENV};
+#[cfg(feature = "cli")]
+#[doc(hidden)]
+pub use crate::run::main as __cargo_tempdir_test_main;
+#[doc(hidden)]
+pub use crate::snapshot::assert_tree_eq_impl as __assert_tree_eq;
+pub use crate::snapshot::{EntryKind, Snapshot, SnapshotEntry};
+pub use crate::trash::wait_for_deferred_removals;
+pub use crate::usage::DiskUsage;
+
+/// Attribute macros.
+///
+/// Requires the `macros` feature.
+#[cfg(feature = "macros")]
+pub mod macros {
+    /// Define a test function that receives a temporary directory.
+    ///
+    /// The function takes a single argument, either `dir: TempDir`,
+    /// `dir: &TempDir` or `dir: &Path`. The directory is created with
+    /// [`TempDirBuilder::build_with_path`](crate::TempDirBuilder::build_with_path)
+    /// using the test function name, so it lives at
+    /// `root/test-<uuid>/<test name>`, and is cleaned up with
+    /// [`TempDir::keep_on_failure`](crate::TempDir::keep_on_failure).
+    ///
+    /// By default, roots are tried in the order `CARGO_TARGET_TMPDIR`,
+    /// `OUT_DIR`. Use `builder = <expr>` to choose a different
+    /// [`TempDirBuilder`](crate::TempDirBuilder) chain.
+    ///
+    /// ```ignore
+    /// use outdir_tempdir::TempDir;
+    /// use std::path::Path;
+    ///
+    /// #[outdir_tempdir::test]
+    /// fn writes_output(dir: &Path) {
+    ///     std::fs::write(dir.join("output.txt"), "ok").unwrap();
+    /// }
+    ///
+    /// #[outdir_tempdir::test(builder = TempDir::builder().env("TMPDIR").out_dir())]
+    /// fn uses_tmpdir_first(dir: TempDir) {
+    ///     assert!(dir.path().ends_with("uses_tmpdir_first"));
+    /// }
+    /// ```
+    ///
+    /// Only panics count as failures. A test that returns `Err` has its
+    /// directory removed.
+    ///
+    /// The macro is re-exported at the crate root, so `use outdir_tempdir::*;`
+    /// shadows the built-in `#[test]`.
+    pub use outdir_tempdir_macros::test;
+}
+
+#[cfg(feature = "macros")]
+pub use crate::macros::test;
+
+use std::ffi::{OsStr, OsString};
+use std::fmt;
 use std::fs;
+use std::io;
 use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime};
 use uuid::Uuid;
 
 /// Root candidate used to create temporary directories.
-#[derive(Clone)]
-enum TempDirRoot {
+///
+/// The textual form used by [`ROOTS_ENV`] and `outdir-tempdir.toml` is
+/// available through [`Display`](fmt::Display) and [`FromStr`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(into = "String", try_from = "String")
+)]
+pub enum TempDirRoot {
     /// Use a root from an environment variable.
     Env(String),
 
@@ -193,6 +303,61 @@ enum TempDirRoot {
 
     /// Use Cargo's CARGO_TARGET_TMPDIR.
     CargoTargetTmp,
+
+    /// Use an explicit path.
+    Path(PathBuf),
+}
+
+impl fmt::Display for TempDirRoot {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TempDirRoot::Env(name) => write!(formatter, "env:{name}"),
+            TempDirRoot::PlatformTempDir => write!(formatter, "platform-temp-dir"),
+            TempDirRoot::Out => write!(formatter, "out-dir"),
+            TempDirRoot::CargoTargetTmp => write!(formatter, "cargo-target-tmpdir"),
+            TempDirRoot::Path(path) => write!(formatter, "path:{}", path.display()),
+        }
+    }
+}
+
+impl From<TempDirRoot> for String {
+    fn from(root: TempDirRoot) -> Self {
+        root.to_string()
+    }
+}
+
+impl TryFrom<String> for TempDirRoot {
+    type Error = Error;
+
+    fn try_from(spec: String) -> Result<Self> {
+        spec.parse()
+    }
+}
+
+impl FromStr for TempDirRoot {
+    type Err = Error;
+
+    /// Parse a root candidate from its textual form.
+    ///
+    /// The accepted forms are `env:<NAME>`, `path:<PATH>`,
+    /// `platform-temp-dir`, `cargo-target-tmpdir` and `out-dir`.
+    fn from_str(spec: &str) -> Result<Self> {
+        let root = match spec {
+            "platform-temp-dir" => Some(TempDirRoot::PlatformTempDir),
+            "cargo-target-tmpdir" => Some(TempDirRoot::CargoTargetTmp),
+            "out-dir" => Some(TempDirRoot::Out),
+            spec => {
+                if let Some(name) = spec.strip_prefix("env:") {
+                    (!name.is_empty()).then(|| TempDirRoot::Env(name.to_string()))
+                } else if let Some(path) = spec.strip_prefix("path:") {
+                    (!path.is_empty()).then(|| TempDirRoot::Path(PathBuf::from(path)))
+                } else {
+                    None
+                }
+            }
+        };
+        root.ok_or_else(|| Error::InvalidRootSpec(spec.to_string()))
+    }
 }
 
 /// Represents a temporary directory created under a selected root directory.
@@ -200,10 +365,27 @@ enum TempDirRoot {
 /// The directory is removed when this value is dropped only if automatic removal
 /// has been enabled by calling [`TempDir::autorm`].
 pub struct TempDir {
+    root_kind: TempDirRoot,
     root: PathBuf,
     remove_target_rel: PathBuf,
     full: PathBuf,
+    /// `false` for a directory owned by another process, see [`TempDir::from_env`].
+    owned: bool,
     autorm: bool,
+    keep_on_failure: bool,
+    deferred: bool,
+    removal_retry: remove::RemovalRetry,
+    entered: AtomicUsize,
+    report_id: Option<usize>,
+    /// `true` once [`TempDir::close`] has removed the directory and recorded the outcome.
+    closed: bool,
+    max_bytes: Option<u64>,
+    quota_watchdog: Option<quota::Watchdog>,
+    created_at: SystemTime,
+    /// Released after removal, since fields are dropped after `Drop::drop`.
+    lock: Option<lock::DirLock>,
+    #[cfg(feature = "tokio")]
+    async_drop: bool,
 }
 
 impl TempDir {
@@ -300,9 +482,89 @@ impl TempDir {
     fn with_path_safe_in<P: AsRef<Path>>(path: P, root: TempDirRoot) -> Result<Self> {
         let path = path.as_ref();
         let target = Self::cleanse_relative_path(path)?;
-        let target_root = target_root(root)?;
+        let target_root = target_root(root.clone())?;
 
-        Self::create_in_root(path, &target, target_root)
+        Self::create_in_root(path, &target, root, target_root)
+    }
+
+    /// Create a named temporary directory under `OUT_DIR` that can be shared between processes.
+    ///
+    /// Test binaries in a workspace run in parallel, so a fixed path such as
+    /// `shared-cache` may be used by several processes at once. This takes an
+    /// advisory lock on a lock file next to the top-level directory, for
+    /// example `OUT_DIR/shared-cache.lock`, before creating the directory.
+    ///
+    /// With [`LockMode::Shared`], [`TempDir::autorm`] removes the directory
+    /// only when the last holder releases it. With [`LockMode::Exclusive`],
+    /// other processes wait until this value is dropped. The lock file itself
+    /// is left in place.
+    ///
+    /// # Panics
+    ///
+    /// This function panics under the same conditions as [`TempDir::with_path`].
+    pub fn with_path_locked<P: AsRef<Path>>(path: P, mode: LockMode) -> Self {
+        Self::with_path_locked_safe(path, mode).unwrap()
+    }
+
+    /// Create a named temporary directory under `OUT_DIR` that can be shared between processes.
+    ///
+    /// See [`TempDir::with_path_locked`].
+    ///
+    /// # Errors
+    ///
+    /// The same errors as [`TempDir::with_path_safe`] are returned.
+    /// If the lock file cannot be created or locked, it will lead to an `Io` error.
+    pub fn with_path_locked_safe<P: AsRef<Path>>(path: P, mode: LockMode) -> Result<Self> {
+        Self::with_path_locked_safe_in(path, mode, TempDirRoot::Out)
+    }
+
+    /// Create a named temporary directory under `CARGO_TARGET_TMPDIR` that can be shared between processes.
+    ///
+    /// See [`TempDir::with_path_locked`].
+    ///
+    /// # Panics
+    ///
+    /// This function panics under the same conditions as [`TempDir::with_path_in_target_tmp`].
+    pub fn with_path_locked_in_target_tmp<P: AsRef<Path>>(path: P, mode: LockMode) -> Self {
+        Self::with_path_locked_safe_in_target_tmp(path, mode).unwrap()
+    }
+
+    /// Create a named temporary directory under `CARGO_TARGET_TMPDIR` that can be shared between processes.
+    ///
+    /// See [`TempDir::with_path_locked`].
+    ///
+    /// # Errors
+    ///
+    /// The same errors as [`TempDir::with_path_safe_in_target_tmp`] are returned.
+    /// If the lock file cannot be created or locked, it will lead to an `Io` error.
+    pub fn with_path_locked_safe_in_target_tmp<P: AsRef<Path>>(
+        path: P,
+        mode: LockMode,
+    ) -> Result<Self> {
+        Self::with_path_locked_safe_in(path, mode, TempDirRoot::CargoTargetTmp)
+    }
+
+    fn with_path_locked_safe_in<P: AsRef<Path>>(
+        path: P,
+        mode: LockMode,
+        root: TempDirRoot,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        let target = Self::cleanse_relative_path(path)?;
+        let target_root = target_root(root.clone())?;
+        let Some(top) = target.iter().next() else {
+            return Err(Error::InvalidPath(path.to_path_buf()));
+        };
+
+        let mut lock_name = top.to_os_string();
+        lock_name.push(".lock");
+        // Lock before creating the directory, so that it cannot be removed by
+        // the last holder between its creation and this process taking the lock.
+        let lock = lock::DirLock::acquire(&target_root.join(lock_name), mode)?;
+
+        let mut tempdir = Self::create_in_root(path, &target, root, target_root)?;
+        tempdir.lock = Some(lock);
+        Ok(tempdir)
     }
 
     /// Enable automatic removal when this value is dropped.
@@ -311,36 +573,409 @@ impl TempDir {
         self
     }
 
+    /// Enable automatic removal when this value is dropped, unless the current thread is panicking.
+    ///
+    /// A failed test keeps its directory for inspection, and its path is
+    /// printed to standard error. A passing test removes it as with
+    /// [`TempDir::autorm`].
+    pub fn keep_on_failure(mut self) -> Self {
+        self.autorm = true;
+        self.keep_on_failure = true;
+        self
+    }
+
+    /// Enable automatic removal in the background when this value is dropped.
+    ///
+    /// On drop, the directory is renamed to a hidden `.trash-<uuid>` sibling in
+    /// the same root, which is cheap, and then removed on a background thread.
+    /// This keeps tests that create large trees from blocking on removal.
+    ///
+    /// Directories still pending at process exit are removed before the
+    /// process exits on Unix. On other platforms, call
+    /// [`wait_for_deferred_removals`] to make sure they are gone. If the rename
+    /// fails, the directory is removed synchronously instead.
+    ///
+    /// [`Event::Deferred`] is emitted when the directory is renamed, and
+    /// [`Event::Removed`] once the background thread has removed it.
+    pub fn autorm_deferred(mut self) -> Self {
+        self.autorm = true;
+        self.deferred = true;
+        self
+    }
+
+    /// Retry failed removal on drop and in [`TempDir::close`].
+    ///
+    /// Removal is attempted up to `attempts` times in total. The first retry
+    /// waits `backoff`, and the delay doubles after each further attempt. This
+    /// helps when a child process is still exiting and holding files, or when
+    /// entries are briefly recreated while the directory is being removed.
+    ///
+    /// By default, removal is attempted once.
+    pub fn removal_retries(mut self, attempts: u32, backoff: Duration) -> Self {
+        self.removal_retry = remove::RemovalRetry::new(attempts, backoff);
+        self
+    }
+
+    /// Remove the temporary directory now.
+    ///
+    /// This removes the same directory as [`TempDir::autorm`] would, whether
+    /// or not automatic removal is enabled, and reports failures as errors
+    /// instead of panicking.
+    ///
+    /// Removal never follows symbolic links. Links inside the directory are
+    /// removed themselves. If the directory, or one of its parents below the
+    /// selected root, has been replaced with a symbolic link, nothing is removed.
+    /// On Linux, removal is performed relative to open directory handles and
+    /// also refuses to cross into other mounted file systems.
+    ///
+    /// A directory created with [`LockMode::Shared`] is kept if another
+    /// process still holds the lock. In that case, this returns `Ok(())`
+    /// without removing anything, and the last holder removes the directory
+    /// when it is closed or dropped.
+    ///
+    /// # Errors
+    ///
+    /// If a symbolic link is found in the path to remove, it will lead to a `SymlinkInRemovalPath` error.
+    /// If a mount point is found inside the directory, it will lead to a `MountPointInRemovalPath` error.
+    /// If the directory cannot be removed after all attempts configured with
+    /// [`TempDir::removal_retries`], it will lead to a `RemovalFailed` error with the number of attempts.
+    /// If the directory is owned by another process, see [`TempDir::from_env`], it will lead to a `NotOwner` error.
+    /// If the quota watchdog found a violation, the directory is still removed,
+    /// and then it will lead to a `QuotaExceeded` error.
+    pub fn close(mut self) -> Result<()> {
+        if !self.owned {
+            return Err(Error::NotOwner(self.full.clone()));
+        }
+
+        self.autorm = false;
+        let exceeded = self.quota_watchdog.take().and_then(quota::Watchdog::stop);
+        if self
+            .lock
+            .as_ref()
+            .is_some_and(|lock| !lock.is_last_holder())
+        {
+            // Another process still uses the shared directory.
+            return exceeded.map_or(Ok(()), Err);
+        }
+        let result = self.pending_removal().remove();
+        // Already recorded, so dropping `self` must not record it again as kept.
+        self.closed = true;
+        result?;
+        exceeded.map_or(Ok(()), Err)
+    }
+
     /// Get the path to the temporary directory.
     pub fn path(&self) -> &Path {
         self.full.as_path()
     }
+
+    /// Get metadata describing this temporary directory.
+    pub fn info(&self) -> TempDirInfo {
+        TempDirInfo::new(
+            self.full.clone(),
+            self.root_kind.clone(),
+            self.created_at,
+            self.autorm,
+        )
+    }
+
+    /// Change the process current directory to the temporary directory.
+    ///
+    /// The previous current directory is restored when the returned guard is
+    /// dropped. Because the current directory is process-wide, guards are
+    /// serialised by a global lock: calls from other threads block until the
+    /// active guard is dropped.
+    ///
+    /// While a guard is active, automatic removal on drop is skipped.
+    ///
+    /// # Errors
+    ///
+    /// If a guard is already alive on the current thread, it will lead to a `CurrentDirAlreadyEntered` error.
+    /// If the current directory cannot be read or changed, it will lead to an `Io` error.
+    pub fn enter(&self) -> Result<CurrentDirGuard<'_>> {
+        CurrentDirGuard::enter(self.path(), &self.entered)
+    }
+
+    /// Create a command that runs inside the temporary directory.
+    ///
+    /// The command's current directory is set to [`TempDir::path`], and
+    /// `TMPDIR`, `TEMP` and `TMP` are set to a private `.tmp` subdirectory, so
+    /// temporary files of the spawned program are removed together with this
+    /// directory by [`TempDir::autorm`]. On Unix, `.tmp` is created with mode
+    /// `0o700`.
+    ///
+    /// `.tmp` is an ordinary subdirectory, so it appears in
+    /// [`TempDir::snapshot`] and is compared by [`TempDir::assert_golden`].
+    /// Remove it before taking a snapshot if the program's temporary files
+    /// are not part of the expected output.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if the `.tmp` subdirectory cannot be created.  
+    /// (because testing cannot proceed)
+    pub fn command<S: AsRef<OsStr>>(&self, program: S) -> Command {
+        self.command_safe(program).unwrap()
+    }
+
+    /// Create a command that runs inside the temporary directory.
+    ///
+    /// See [`TempDir::command`] for the configured environment.
+    ///
+    /// # Errors
+    ///
+    /// If the `.tmp` subdirectory cannot be created, it will lead to an `Io` error.
+    pub fn command_safe<S: AsRef<OsStr>>(&self, program: S) -> Result<Command> {
+        command::command_in(self.path(), program)
+    }
+
+    /// Create isolated home and XDG base directories inside the temporary directory.
+    ///
+    /// This creates `home`, `config`, `cache`, `data` and `runtime`
+    /// subdirectories. Use [`IsolatedHome::apply`] to point a subprocess's
+    /// `HOME`, `XDG_CONFIG_HOME`, `XDG_CACHE_HOME`, `XDG_DATA_HOME` and
+    /// `XDG_RUNTIME_DIR` at them.
+    ///
+    /// # Errors
+    ///
+    /// If the subdirectories cannot be created, it will lead to an `Io` error.
+    pub fn isolated_home(&self) -> Result<IsolatedHome> {
+        IsolatedHome::create(self.path())
+    }
+
+    /// Measure the disk usage of the directory tree.
+    ///
+    /// The tree is walked without following symbolic links. Hard-linked files
+    /// are counted once.
+    ///
+    /// # Errors
+    ///
+    /// If the directory tree cannot be read, it will lead to an `Io` error.
+    pub fn disk_usage(&self) -> Result<DiskUsage> {
+        DiskUsage::measure(self.path())
+    }
+
+    /// Check the size quota set by [`TempDirBuilder::max_bytes`].
+    ///
+    /// The quota covers all regular files the directory owns, that is, the
+    /// directory that [`TempDir::autorm`] would remove. Their size is measured
+    /// in the same way as [`TempDir::disk_usage`], so hard-linked files are
+    /// counted once. Without a quota, this always succeeds.
+    ///
+    /// If the watchdog set by [`TempDirBuilder::quota_watchdog`] has already
+    /// found a violation, it is returned without walking the tree again. Code
+    /// that writes in a loop can call this to stop once the quota is exceeded.
+    ///
+    /// # Errors
+    ///
+    /// If the quota is exceeded, it will lead to a `QuotaExceeded` error listing the largest files.
+    /// If the directory tree cannot be read, it will lead to an `Io` error.
+    pub fn check_quota(&self) -> Result<()> {
+        if let Some(error) = self
+            .quota_watchdog
+            .as_ref()
+            .and_then(quota::Watchdog::exceeded)
+        {
+            return Err(error);
+        }
+
+        match self.max_bytes {
+            Some(max_bytes) => quota::check(&self.removal_target(), max_bytes),
+            None => Ok(()),
+        }
+    }
+
+    /// Take a deterministic listing of the directory tree.
+    ///
+    /// The listing contains paths, entry kinds and file sizes. Symbolic links
+    /// are recorded but not followed. Compare it against an expected listing
+    /// with [`assert_tree_eq!`].
+    ///
+    /// # Errors
+    ///
+    /// If the directory tree cannot be read, it will lead to an `Io` error.
+    pub fn snapshot(&self) -> Result<Snapshot> {
+        Snapshot::capture(self.path(), false)
+    }
+
+    /// Take a deterministic listing of the directory tree, including content hashes.
+    ///
+    /// Same as [`TempDir::snapshot`], but each file line also contains a hash of
+    /// the file contents.
+    ///
+    /// # Errors
+    ///
+    /// If the directory tree or a file cannot be read, it will lead to an `Io` error.
+    pub fn snapshot_with_hashes(&self) -> Result<Snapshot> {
+        Snapshot::capture(self.path(), true)
+    }
+
+    /// Compare the directory tree against an expected directory.
+    ///
+    /// Files are compared by contents, symbolic links by target. A missing
+    /// `expected` directory is treated as empty.
+    ///
+    /// # Errors
+    ///
+    /// If either directory tree cannot be read, it will lead to an `Io` error.
+    pub fn compare_with_dir<P: AsRef<Path>>(&self, expected: P) -> Result<GoldenDiff> {
+        GoldenDiff::compare(self.path(), expected.as_ref())
+    }
+
+    /// Assert that the directory tree matches an expected directory.
+    ///
+    /// Relative `expected` paths are resolved against the current directory,
+    /// which is the package root under `cargo test`.
+    ///
+    /// If the [`BLESS_ENV`] environment variable (`OUTDIR_TEMPDIR_BLESS`) is set
+    /// to a value other than empty or `0`, the expected directory is replaced
+    /// with a copy of the directory tree instead. Blessing is refused if
+    /// `expected` is empty, is the current or package directory or one of
+    /// their ancestors, or overlaps this temporary directory.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if the trees differ, listing added, missing and
+    /// differing paths, or if either tree cannot be read or written.
+    #[track_caller]
+    pub fn assert_golden<P: AsRef<Path>>(&self, expected: P) {
+        let expected = expected.as_ref();
+        if golden::bless_requested() {
+            if let Err(error) = golden::bless(self.path(), expected) {
+                panic!("failed to bless \"{}\": {error}", expected.display());
+            }
+            return;
+        }
+
+        let diff = match self.compare_with_dir(expected) {
+            Ok(diff) => diff,
+            Err(error) => panic!("failed to compare with \"{}\": {error}", expected.display()),
+        };
+
+        if !diff.is_empty() {
+            panic!(
+                "directory tree does not match \"{}\" (set {BLESS_ENV}=1 to update):\n{diff}",
+                expected.display()
+            );
+        }
+    }
 }
 
 impl Drop for TempDir {
     /// Remove the temporary directory if automatic removal is enabled.
+    ///
+    /// Removal is skipped while a [`CurrentDirGuard`] for this directory is active.
     fn drop(&mut self) {
-        if self.autorm {
-            let rmdir = self.root.join(&self.remove_target_rel);
-            match fs::remove_dir_all(rmdir) {
-                Ok(()) => {}
-                Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
-                Err(error) => panic!("{error}"),
+        // Stop checking the quota before the tree starts disappearing.
+        let exceeded = self.quota_watchdog.take().and_then(quota::Watchdog::stop);
+        self.finish_on_drop();
+
+        // Fail the test with the violation found by the watchdog, after the
+        // directory has been handled as usual.
+        if let Some(error) = exceeded {
+            if !std::thread::panicking() {
+                panic!("{error}");
             }
         }
     }
 }
 
+impl TempDir {
+    /// Remove or keep the directory on drop, and record the outcome.
+    fn finish_on_drop(&mut self) {
+        if self.closed {
+            return;
+        }
+
+        // Kept directories are measured when the report is written, and
+        // removed ones just before removal, on the thread that removes them.
+        if !self.owned || !self.autorm || self.entered.load(Ordering::SeqCst) != 0 {
+            report::finish(self.report_id, None, report::Outcome::Kept);
+            event::emit(Event::Kept { path: &self.full });
+            return;
+        }
+
+        if self.keep_on_failure && std::thread::panicking() {
+            eprintln!(
+                "outdir-tempdir: keeping \"{}\" because the test failed",
+                self.path().display()
+            );
+            report::finish(self.report_id, None, report::Outcome::Kept);
+            event::emit(Event::Kept { path: &self.full });
+            return;
+        }
+
+        if self
+            .lock
+            .as_ref()
+            .is_some_and(|lock| !lock.is_last_holder())
+        {
+            report::finish(self.report_id, None, report::Outcome::Kept);
+            event::emit(Event::Kept { path: &self.full });
+            return;
+        }
+
+        // Locked directories are removed before the lock is released, so they
+        // are never offloaded.
+        #[cfg(feature = "tokio")]
+        if self.async_drop && self.lock.is_none() && async_api::offload_removal(self) {
+            return;
+        }
+
+        if let Err(error) = Self::remove_on_drop(self.pending_removal(), self.deferred) {
+            panic!("{error}");
+        }
+    }
+}
+
 impl Default for TempDir {
     fn default() -> Self {
         Self::new()
     }
 }
 
+/// Removal of a dropped directory, which may run on another thread.
+pub(crate) struct PendingRemoval {
+    pub(crate) root: PathBuf,
+    pub(crate) rel: PathBuf,
+    /// Path reported in events.
+    pub(crate) path: PathBuf,
+    pub(crate) retry: remove::RemovalRetry,
+    pub(crate) report_id: Option<usize>,
+}
+
+impl PendingRemoval {
+    /// Remove `root/rel` now and record the outcome.
+    ///
+    /// If the report is enabled, the tree is measured first, on the calling
+    /// thread, which is the background thread for deferred removal.
+    pub(crate) fn remove(self) -> Result<()> {
+        let size = report::measure(self.report_id, &self.root.join(&self.rel));
+        let result = remove::remove_tree(&self.root, &self.rel, &self.retry);
+        record_removal(self.report_id, size, &self.path, &result);
+        result
+    }
+}
+
+/// Record the outcome of removing a directory in the report and as an event.
+fn record_removal(report_id: Option<usize>, size: Option<u64>, path: &Path, result: &Result<()>) {
+    match result {
+        Ok(()) => {
+            report::finish(report_id, size, report::Outcome::Removed);
+            event::emit(Event::Removed { path });
+        }
+        Err(error) => {
+            report::finish(report_id, size, report::Outcome::Failed(error));
+            event::emit(Event::RemovalFailed { path, error });
+        }
+    }
+}
+
 /// Get the selected root directory from the given root candidate.
 fn target_root(root: TempDirRoot) -> Result<PathBuf> {
     TempDir::root_path_if_available(&root).ok_or(match root {
-        TempDirRoot::Env(_) | TempDirRoot::PlatformTempDir => Error::NoRootCandidatesAvailable,
+        TempDirRoot::Env(_) | TempDirRoot::PlatformTempDir | TempDirRoot::Path(_) => {
+            Error::NoRootCandidatesAvailable
+        }
         TempDirRoot::Out => Error::OutDirNotFound,
         TempDirRoot::CargoTargetTmp => Error::CargoTargetTmpDirNotFound,
     })
@@ -372,18 +1007,32 @@ fn cleansing_path<P: AsRef<Path>>(path: P) -> Result<PathBuf> {
 }
 
 impl TempDir {
-    fn create_in_root(path: &Path, target: &Path, target_root: PathBuf) -> Result<Self> {
+    fn create_in_root(
+        path: &Path,
+        target: &Path,
+        root_kind: TempDirRoot,
+        target_root: PathBuf,
+    ) -> Result<Self> {
         let Some(remove_target_rel) = top_level_component_path(target) else {
             return Err(Error::InvalidPath(path.to_path_buf()));
         };
-        Self::create_in_root_with_removal(path, target, target_root, remove_target_rel)
+        Self::create_in_root_with_removal(
+            path,
+            target,
+            root_kind,
+            target_root,
+            remove_target_rel,
+            DirPermissions::default(),
+        )
     }
 
     fn create_in_root_with_removal(
         path: &Path,
         target: &Path,
+        root_kind: TempDirRoot,
         target_root: PathBuf,
         remove_target_rel: PathBuf,
+        permissions: DirPermissions,
     ) -> Result<Self> {
         let target_full_path = target_root.join(target);
 
@@ -391,24 +1040,145 @@ impl TempDir {
             return Err(Error::InvalidPath(path.to_path_buf()));
         }
 
-        fs::create_dir_all(target_full_path.as_path())?;
+        create_dirs(&target_root, target, permissions)?;
+        let report_id = report::register(&target_full_path, &target_root);
+        event::emit(Event::Created {
+            path: &target_full_path,
+            root: &target_root,
+        });
 
-        Ok(Self {
-            root: target_root,
+        Ok(Self::from_parts(
+            root_kind,
+            target_root,
+            remove_target_rel,
+            target_full_path,
+            report_id,
+        ))
+    }
+
+    /// Create an owned value for an existing directory, with every option at its default.
+    pub(crate) fn from_parts(
+        root_kind: TempDirRoot,
+        root: PathBuf,
+        remove_target_rel: PathBuf,
+        full: PathBuf,
+        report_id: Option<usize>,
+    ) -> Self {
+        Self {
+            root_kind,
+            root,
             remove_target_rel,
-            full: target_full_path,
+            full,
+            owned: true,
             autorm: false,
-        })
+            keep_on_failure: false,
+            deferred: false,
+            removal_retry: remove::RemovalRetry::default(),
+            entered: AtomicUsize::new(0),
+            report_id,
+            closed: false,
+            max_bytes: None,
+            quota_watchdog: None,
+            created_at: SystemTime::now(),
+            lock: None,
+            #[cfg(feature = "tokio")]
+            async_drop: false,
+        }
+    }
+
+    fn set_quota(&mut self, max_bytes: u64, watchdog: Option<Duration>, exit: bool) -> Result<()> {
+        self.max_bytes = Some(max_bytes);
+        if let Some(interval) = watchdog {
+            let dir = self.removal_target();
+            self.quota_watchdog = Some(quota::Watchdog::start(dir, max_bytes, interval, exit)?);
+        }
+        Ok(())
+    }
+
+    /// Get the directory removed by [`TempDir::autorm`] and [`TempDir::close`].
+    fn removal_target(&self) -> PathBuf {
+        self.root.join(&self.remove_target_rel)
     }
 
-    fn cleanse_relative_path(path: &Path) -> Result<PathBuf> {
+    fn pending_removal(&self) -> PendingRemoval {
+        PendingRemoval {
+            root: self.root.clone(),
+            rel: self.remove_target_rel.clone(),
+            path: self.removal_target(),
+            retry: self.removal_retry,
+            report_id: self.report_id,
+        }
+    }
+
+    /// Remove a dropped directory and record the outcome.
+    ///
+    /// With `deferred`, the outcome is recorded by the background worker once
+    /// the directory is actually removed.
+    fn remove_on_drop(mut removal: PendingRemoval, deferred: bool) -> Result<()> {
+        if deferred {
+            match trash::move_to_trash(&mut removal) {
+                Ok(()) => {
+                    trash::defer_removal(removal);
+                    return Ok(());
+                }
+                Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                    record_removal(removal.report_id, None, &removal.path, &Ok(()));
+                    return Ok(());
+                }
+                Err(_) => {}
+            }
+        }
+
+        removal.remove()
+    }
+
+    pub(crate) fn cleanse_relative_path(path: &Path) -> Result<PathBuf> {
         cleansing_path(path)
     }
 
+    /// Validate a root taken from the environment or the platform.
+    ///
+    /// # Errors
+    ///
+    /// A relative root will result in a `RootNotAbsolute` error.
+    /// If the root cannot be canonicalised, for example because it does not exist, it will lead to an `Io` error.
+    /// A root resolving to the filesystem root will result in a `RootIsFilesystemRoot` error,
+    /// and one resolving to the user's home directory will result in a `RootIsHomeDir` error.
+    /// If `reject_world_writable` is set, a world-writable root without the sticky bit will result in a `RootWorldWritable` error.
+    pub(crate) fn validate_root(root: PathBuf, reject_world_writable: bool) -> Result<PathBuf> {
+        if !root.is_absolute() {
+            return Err(Error::RootNotAbsolute(root));
+        }
+
+        let canonical = root.canonicalize()?;
+        if canonical.parent().is_none() {
+            return Err(Error::RootIsFilesystemRoot(root));
+        }
+
+        let home = std::env::home_dir().and_then(|home| home.canonicalize().ok());
+        if home.as_ref() == Some(&canonical) {
+            return Err(Error::RootIsHomeDir(root));
+        }
+
+        if reject_world_writable && is_world_writable_without_sticky(&canonical.metadata()?) {
+            return Err(Error::RootWorldWritable(root));
+        }
+
+        Ok(canonical)
+    }
+
     fn root_path_if_available(root: &TempDirRoot) -> Option<PathBuf> {
+        Self::root_path_with(root, &|name| std::env::var_os(name))
+    }
+
+    /// Same as [`TempDir::root_path_if_available`], reading environment variables through `var`.
+    fn root_path_with(
+        root: &TempDirRoot,
+        var: &dyn Fn(&str) -> Option<OsString>,
+    ) -> Option<PathBuf> {
         match root {
             TempDirRoot::Env(name) => {
-                let value = std::env::var_os(name)?;
+                let value = var(name)?;
                 if value.is_empty() {
                     return None;
                 }
@@ -417,11 +1187,124 @@ impl TempDir {
             }
             TempDirRoot::PlatformTempDir => Some(std::env::temp_dir()),
             TempDirRoot::Out => Some(PathBuf::from(std::env!("OUT_DIR"))),
-            TempDirRoot::CargoTargetTmp => {
-                std::env::var_os("CARGO_TARGET_TMPDIR").map(PathBuf::from)
+            TempDirRoot::CargoTargetTmp => var("CARGO_TARGET_TMPDIR").map(PathBuf::from),
+            TempDirRoot::Path(path) => Some(path.clone()),
+        }
+    }
+}
+
+/// Permissions applied to directories created under the selected root.
+///
+/// `None` leaves the permissions to the process umask. Modes are only applied on Unix.
+#[derive(Clone, Copy, Default)]
+struct DirPermissions {
+    /// Mode of the top-level directory created under the root.
+    top: Option<u32>,
+
+    /// Mode of the directories created below the top-level directory.
+    nested: Option<u32>,
+}
+
+/// Create `target` under `target_root` one level at a time, applying `permissions`
+/// to each level that is newly created.
+///
+/// New levels are created owner-only and get their requested mode at the end,
+/// deepest level first, so that a mode without owner write or search
+/// permission, such as `0o500`, does not prevent creating the levels below it.
+fn create_dirs(target_root: &Path, target: &Path, permissions: DirPermissions) -> io::Result<()> {
+    if permissions.top.is_none() && permissions.nested.is_none() {
+        return fs::create_dir_all(target_root.join(target));
+    }
+
+    fs::create_dir_all(target_root)?;
+
+    let mut created = Vec::new();
+    let mut path = target_root.to_path_buf();
+    for (level, component) in target.iter().enumerate() {
+        path.push(component);
+        let mode = if level == 0 {
+            permissions.top
+        } else {
+            permissions.nested
+        };
+
+        match create_dir_private(&path, mode.is_some()) {
+            Ok(()) => {
+                if let Some(mode) = mode {
+                    created.push((path.clone(), mode));
+                }
             }
+            Err(error) if error.kind() == io::ErrorKind::AlreadyExists && path.is_dir() => {}
+            Err(error) => return Err(error),
         }
     }
+
+    for (path, mode) in created.iter().rev() {
+        set_dir_mode(path, *mode)?;
+    }
+
+    Ok(())
+}
+
+/// Create a directory and its missing parents, accessible only by its owner on Unix.
+///
+/// An existing directory is kept, and its permissions are reset to `0o700`.
+pub(crate) fn create_private_dir(path: &Path) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    match create_dir_private(path, true) {
+        Ok(()) => {}
+        Err(error) if error.kind() == io::ErrorKind::AlreadyExists && path.is_dir() => {}
+        Err(error) => return Err(error),
+    }
+    set_dir_mode(path, 0o700)
+}
+
+/// Create a directory, owner-only if `private` is set.
+#[cfg(unix)]
+fn create_dir_private(path: &Path, private: bool) -> io::Result<()> {
+    use std::os::unix::fs::DirBuilderExt;
+
+    if !private {
+        return fs::create_dir(path);
+    }
+
+    // Create owner-only to avoid a window with wider permissions. The final
+    // mode is set by `set_dir_mode`, which also ignores the umask.
+    fs::DirBuilder::new().mode(0o700).create(path)
+}
+
+#[cfg(not(unix))]
+fn create_dir_private(path: &Path, _private: bool) -> io::Result<()> {
+    fs::create_dir(path)
+}
+
+#[cfg(unix)]
+fn set_dir_mode(path: &Path, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn set_dir_mode(_path: &Path, _mode: u32) -> io::Result<()> {
+    Ok(())
+}
+
+/// Returns `true` if the directory is world-writable without the sticky bit.
+#[cfg(unix)]
+fn is_world_writable_without_sticky(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = metadata.permissions().mode();
+    mode & 0o002 != 0 && mode & 0o1000 == 0
+}
+
+#[cfg(not(unix))]
+fn is_world_writable_without_sticky(_metadata: &fs::Metadata) -> bool {
+    false
 }
 
 fn top_level_component_path(path: &Path) -> Option<PathBuf> {
@@ -431,7 +1314,12 @@ fn top_level_component_path(path: &Path) -> Option<PathBuf> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    // The glob import above brings in the `test` attribute macro, which
+    // would otherwise shadow the built-in one.
+    use std::collections::HashMap;
     use std::path::MAIN_SEPARATOR;
+    #[cfg(feature = "macros")]
+    use std::prelude::rust_2021::test;
 
     #[test]
     fn test_cleansing_path() {
@@ -544,7 +1432,7 @@ mod tests {
 
     #[test]
     fn test_builder_prefers_platform_temp_dir_then_out_dir() {
-        let temp_root = std::env::temp_dir();
+        let temp_root = std::env::temp_dir().canonicalize().unwrap();
         let rmdir = {
             let temp = TempDir::builder()
                 .platform_temp_dir()
@@ -565,7 +1453,7 @@ mod tests {
 
     #[test]
     fn test_builder_build_with_path() {
-        let temp_root = std::env::temp_dir();
+        let temp_root = std::env::temp_dir().canonicalize().unwrap();
         let rmdir = {
             let temp = TempDir::builder()
                 .platform_temp_dir()
@@ -670,4 +1558,159 @@ mod tests {
             _ => panic!(),
         }
     }
+
+    /// Build an environment lookup from fixed variables, so that tests never
+    /// modify the process environment while other tests run.
+    fn vars(pairs: &[(&str, &Path)]) -> impl Fn(&str) -> Option<OsString> + Send + Sync {
+        let vars: HashMap<String, OsString> = pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.as_os_str().to_os_string()))
+            .collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    fn test_builder_rejects_unsafe_env_roots() {
+        let build = |root: &Path| {
+            TempDir::builder()
+                .vars(vars(&[("OUTDIR_TEMPDIR_TEST_ROOT", root)]))
+                .env("OUTDIR_TEMPDIR_TEST_ROOT")
+                .out_dir()
+                .build()
+        };
+
+        match build(Path::new("relative/root")) {
+            Err(Error::RootNotAbsolute(path)) => assert_eq!(path, PathBuf::from("relative/root")),
+            _ => panic!(),
+        }
+
+        #[cfg(unix)]
+        match build(Path::new("/")) {
+            Err(Error::RootIsFilesystemRoot(path)) => assert_eq!(path, PathBuf::from("/")),
+            _ => panic!(),
+        }
+
+        if let Some(home) = std::env::home_dir().filter(|home| home.is_dir()) {
+            match build(&home) {
+                Err(Error::RootIsHomeDir(path)) => assert_eq!(path, home),
+                _ => panic!(),
+            }
+        }
+
+        // A missing root falls back to the next candidate.
+        let parent = TempDir::new().autorm();
+        let temp = build(&parent.path().join("missing"))
+            .expect("missing root should fall back to OUT_DIR")
+            .autorm();
+        assert_eq!(temp.root_kind, TempDirRoot::Out);
+    }
+
+    #[test]
+    fn test_builder_from_env() {
+        let root = TempDir::new().autorm();
+        let root_path = root.path().canonicalize().unwrap();
+
+        let roots = format!(
+            " env:OUTDIR_TEMPDIR_TEST_UNSET_ROOT, path:{} ,out-dir",
+            root_path.display()
+        );
+        let temp = TempDirBuilder::from_vars(vars(&[(ROOTS_ENV, Path::new(&roots))]))
+            .unwrap()
+            .build()
+            .expect("failed to create temporary directory from environment")
+            .autorm();
+        assert_eq!(temp.path().parent(), Some(root_path.as_path()));
+
+        for spec in [
+            "out-dir,",
+            "env:",
+            "path:",
+            "tmp",
+            "cargo-target-tmpdir,,out-dir",
+        ] {
+            match TempDirBuilder::from_vars(vars(&[(ROOTS_ENV, Path::new(spec))])) {
+                Err(Error::InvalidRootSpec(_)) => {}
+                _ => panic!("{spec}"),
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_builder_rejects_world_writable_root() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let parent = TempDir::new().autorm();
+        let root = parent.path().join("shared");
+        fs::create_dir(&root).unwrap();
+        fs::set_permissions(&root, fs::Permissions::from_mode(0o777)).unwrap();
+        let builder = || {
+            TempDir::builder()
+                .vars(vars(&[("OUTDIR_TEMPDIR_TEST_WORLD_WRITABLE_ROOT", &root)]))
+                .env("OUTDIR_TEMPDIR_TEST_WORLD_WRITABLE_ROOT")
+                .reject_world_writable_roots()
+        };
+
+        match builder().build() {
+            Err(Error::RootWorldWritable(path)) => assert_eq!(path, root),
+            _ => panic!(),
+        }
+
+        fs::set_permissions(&root, fs::Permissions::from_mode(0o1777)).unwrap();
+        let temp = builder()
+            .build()
+            .expect("sticky world-writable root should be accepted")
+            .autorm();
+        assert!(temp.path().starts_with(root.canonicalize().unwrap()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_builder_private_root_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mode_of = |path: &Path| path.metadata().unwrap().permissions().mode() & 0o7777;
+        let out_dir = TempDir::root_path_if_available(&TempDirRoot::Out).unwrap();
+
+        let temp = TempDir::builder()
+            .out_dir()
+            .build()
+            .expect("failed to create temporary directory with builder")
+            .autorm();
+        assert_eq!(mode_of(temp.path()), 0o700);
+
+        let temp = TempDir::builder()
+            .out_dir()
+            .mode(0o750)
+            .build_with_path("foo/bar/baz")
+            .expect("failed to create temporary directory with builder mode")
+            .autorm();
+
+        let mut level = out_dir.clone();
+        for component in temp.path().strip_prefix(&out_dir).unwrap() {
+            level.push(component);
+            assert_eq!(mode_of(&level), 0o750, "{}", level.display());
+        }
+
+        // Modes without owner write permission are applied after the nested
+        // levels have been created.
+        let temp = TempDir::builder()
+            .out_dir()
+            .mode(0o500)
+            .build_with_path("foo/bar")
+            .expect("failed to create temporary directory with read-only mode")
+            .autorm();
+        let private_root = temp.removal_target();
+        assert_eq!(mode_of(&private_root), 0o500);
+        assert_eq!(mode_of(temp.path()), 0o500);
+        drop(temp);
+        assert!(!private_root.try_exists().unwrap());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_builder_rejects_invalid_mode() {
+        let result = TempDir::builder().out_dir().mode(0o100_755).build();
+        assert!(matches!(result, Err(Error::InvalidMode(0o100_755))));
+    }
 }
@@ -0,0 +1,195 @@
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::Path;
+
+/// How a directory created with [`TempDir::with_path_locked`](crate::TempDir::with_path_locked) is shared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockMode {
+    /// Share the directory with other holders of a shared lock. It is removed
+    /// only when the last holder releases it.
+    Shared,
+
+    /// Use the directory alone. Other processes wait until it is released.
+    Exclusive,
+}
+
+/// Advisory lock on the lock file of a shared named directory.
+///
+/// The lock is released when this value is dropped.
+pub(crate) struct DirLock {
+    file: File,
+    mode: LockMode,
+}
+
+impl DirLock {
+    /// Open or create `path` and lock it, blocking until the lock is available.
+    pub(crate) fn acquire(path: &Path, mode: LockMode) -> io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        match mode {
+            LockMode::Shared => sys::lock_shared(&file)?,
+            LockMode::Exclusive => sys::lock_exclusive(&file)?,
+        }
+
+        Ok(Self { file, mode })
+    }
+
+    /// Lock an existing lock file exclusively without blocking.
+    ///
+    /// Returns `None` if another process holds the lock.
+    #[cfg(feature = "cli")]
+    pub(crate) fn try_exclusive(path: &Path) -> io::Result<Option<Self>> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        if !sys::try_lock_exclusive(&file)? {
+            return Ok(None);
+        }
+
+        Ok(Some(Self {
+            file,
+            mode: LockMode::Exclusive,
+        }))
+    }
+
+    /// Check whether this is the only holder, and if so keep the lock exclusively.
+    ///
+    /// A shared lock is released and then taken exclusively without blocking.
+    /// If that fails, another holder still uses the directory. Once the
+    /// exclusive lock is taken, new holders wait until it is released, so the
+    /// directory can be removed safely.
+    pub(crate) fn is_last_holder(&self) -> bool {
+        if self.mode == LockMode::Exclusive {
+            return true;
+        }
+
+        if sys::unlock(&self.file).is_err() {
+            return false;
+        }
+        sys::try_lock_exclusive(&self.file).unwrap_or(false)
+    }
+}
+
+/// Advisory file locks with `flock`.
+#[cfg(unix)]
+mod sys {
+    use std::fs::File;
+    use std::io;
+    use std::os::unix::io::AsRawFd;
+
+    fn flock(file: &File, operation: libc::c_int) -> io::Result<()> {
+        loop {
+            if unsafe { libc::flock(file.as_raw_fd(), operation) } == 0 {
+                return Ok(());
+            }
+
+            let error = io::Error::last_os_error();
+            if error.kind() != io::ErrorKind::Interrupted {
+                return Err(error);
+            }
+        }
+    }
+
+    pub(super) fn lock_shared(file: &File) -> io::Result<()> {
+        flock(file, libc::LOCK_SH)
+    }
+
+    pub(super) fn lock_exclusive(file: &File) -> io::Result<()> {
+        flock(file, libc::LOCK_EX)
+    }
+
+    pub(super) fn unlock(file: &File) -> io::Result<()> {
+        flock(file, libc::LOCK_UN)
+    }
+
+    /// Returns `false` if another holder has the lock.
+    pub(super) fn try_lock_exclusive(file: &File) -> io::Result<bool> {
+        match flock(file, libc::LOCK_EX | libc::LOCK_NB) {
+            Ok(()) => Ok(true),
+            Err(error) if error.kind() == io::ErrorKind::WouldBlock => Ok(false),
+            Err(error) => Err(error),
+        }
+    }
+}
+
+/// Advisory file locks from the standard library, available since Rust 1.89.
+#[cfg(not(unix))]
+mod sys {
+    use std::fs::{File, TryLockError};
+    use std::io;
+
+    pub(super) fn lock_shared(file: &File) -> io::Result<()> {
+        file.lock_shared()
+    }
+
+    pub(super) fn lock_exclusive(file: &File) -> io::Result<()> {
+        file.lock()
+    }
+
+    pub(super) fn unlock(file: &File) -> io::Result<()> {
+        file.unlock()
+    }
+
+    /// Returns `false` if another holder has the lock.
+    pub(super) fn try_lock_exclusive(file: &File) -> io::Result<bool> {
+        match file.try_lock() {
+            Ok(()) => Ok(true),
+            Err(TryLockError::WouldBlock) => Ok(false),
+            Err(TryLockError::Error(error)) => Err(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LockMode;
+    use crate::TempDir;
+    use std::fs;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_shared_lock_removes_after_last_holder() {
+        let first = TempDir::with_path_locked("locked-shared/cache", LockMode::Shared).autorm();
+        let second = TempDir::with_path_locked("locked-shared/cache", LockMode::Shared).autorm();
+        assert_eq!(first.path(), second.path());
+
+        fs::write(first.path().join("data.bin"), [0u8; 10]).unwrap();
+        let top = first.removal_target();
+        let lock_file = top.with_file_name("locked-shared.lock");
+
+        drop(first);
+        assert!(second.path().join("data.bin").is_file());
+
+        drop(second);
+        assert!(!top.try_exists().unwrap());
+        assert!(lock_file.is_file());
+    }
+
+    #[test]
+    fn test_exclusive_lock_waits_for_release() {
+        let first = TempDir::with_path_locked("locked-exclusive", LockMode::Exclusive).autorm();
+        fs::write(first.path().join("data.bin"), [0u8; 10]).unwrap();
+
+        let (sender, receiver) = mpsc::channel();
+        let waiter = thread::spawn(move || {
+            let second =
+                TempDir::with_path_locked("locked-exclusive", LockMode::Exclusive).autorm();
+            sender
+                .send(second.path().join("data.bin").exists())
+                .unwrap();
+        });
+
+        assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+        drop(first);
+        assert!(!receiver.recv().unwrap());
+        waiter.join().unwrap();
+    }
+}
@@ -0,0 +1,335 @@
+use crate::{DiskUsage, Error, Result};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Number of files listed in a `QuotaExceeded` error.
+const LARGEST_FILES: usize = 5;
+
+/// Quota violation, kept so that it can be reported more than once.
+#[derive(Debug, Clone)]
+struct Exceeded {
+    limit: u64,
+    used: u64,
+    largest: Vec<(PathBuf, u64)>,
+}
+
+impl From<Exceeded> for Error {
+    fn from(exceeded: Exceeded) -> Self {
+        Error::QuotaExceeded {
+            limit: exceeded.limit,
+            used: exceeded.used,
+            largest: exceeded.largest,
+        }
+    }
+}
+
+/// Check that the tree under `dir` does not use more than `max_bytes`.
+///
+/// # Errors
+///
+/// If the apparent size measured by [`DiskUsage`] exceeds `max_bytes`, it
+/// will lead to a `QuotaExceeded` error listing the largest files.
+/// If the directory tree cannot be read, it will lead to an `Io` error.
+pub(crate) fn check(dir: &Path, max_bytes: u64) -> Result<()> {
+    match measure(dir, max_bytes)? {
+        Some(exceeded) => Err(exceeded.into()),
+        None => Ok(()),
+    }
+}
+
+fn measure(dir: &Path, max_bytes: u64) -> Result<Option<Exceeded>> {
+    let mut files = Vec::new();
+    let usage = DiskUsage::measure_files(dir, |path, len| files.push((path.to_path_buf(), len)))?;
+
+    let used = usage.apparent_bytes();
+    if used <= max_bytes {
+        return Ok(None);
+    }
+
+    files.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    files.truncate(LARGEST_FILES);
+
+    Ok(Some(Exceeded {
+        limit: max_bytes,
+        used,
+        largest: files,
+    }))
+}
+
+struct State {
+    stopped: bool,
+    exceeded: Option<Exceeded>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    wakeup: Condvar,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Background thread that checks the quota periodically.
+///
+/// Once the quota is exceeded, the violation is printed to standard error
+/// and recorded, and the thread stops checking. The violation is reported by
+/// [`TempDir::check_quota`](crate::TempDir::check_quota), and when the
+/// [`TempDir`](crate::TempDir) is closed or dropped. The thread stops when
+/// this value is dropped.
+pub(crate) struct Watchdog {
+    shared: Arc<Shared>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Watchdog {
+    /// Start checking `dir` against `max_bytes` every `interval`.
+    ///
+    /// With `exit`, the process exits with status 101 as soon as the quota is
+    /// exceeded, instead of recording the violation.
+    pub(crate) fn start(
+        dir: PathBuf,
+        max_bytes: u64,
+        interval: Duration,
+        exit: bool,
+    ) -> Result<Self> {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                stopped: false,
+                exceeded: None,
+            }),
+            wakeup: Condvar::new(),
+        });
+        let thread_shared = Arc::clone(&shared);
+
+        let handle = thread::Builder::new()
+            .name("outdir-tempdir-quota".into())
+            .spawn(move || {
+                let shared = &*thread_shared;
+                let mut state = shared.lock();
+                while !state.stopped {
+                    state = shared
+                        .wakeup
+                        .wait_timeout(state, interval)
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .0;
+                    if state.stopped {
+                        break;
+                    }
+
+                    // Walk the tree without holding the lock, so that stopping
+                    // does not wait for a long walk to finish.
+                    drop(state);
+                    // The tree may change while it is walked, so read errors
+                    // are ignored until the next check.
+                    let exceeded = measure(&dir, max_bytes).ok().flatten();
+                    state = shared.lock();
+
+                    if let Some(exceeded) = exceeded {
+                        eprintln!("outdir-tempdir: {}", Error::from(exceeded.clone()));
+                        if exit {
+                            // Same status as a failed test.
+                            std::process::exit(101);
+                        }
+                        state.exceeded = Some(exceeded);
+                        break;
+                    }
+                }
+            })?;
+
+        Ok(Self {
+            shared,
+            handle: Some(handle),
+        })
+    }
+
+    /// Get the violation recorded by the background thread, if any.
+    pub(crate) fn exceeded(&self) -> Option<Error> {
+        self.shared.lock().exceeded.clone().map(Error::from)
+    }
+
+    /// Stop the background thread and return the recorded violation, if any.
+    pub(crate) fn stop(mut self) -> Option<Error> {
+        self.shutdown();
+        self.exceeded()
+    }
+
+    fn shutdown(&mut self) {
+        self.shared.lock().stopped = true;
+        self.shared.wakeup.notify_all();
+
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for Watchdog {
+    /// Stop the background thread and wait for it to finish.
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Error, TempDir};
+    use std::fs;
+    use std::time::Duration;
+
+    #[test]
+    fn test_check_quota() {
+        let temp = TempDir::builder()
+            .out_dir()
+            .max_bytes(1000)
+            .build()
+            .expect("failed to create temporary directory with quota")
+            .autorm();
+
+        fs::write(temp.path().join("small.bin"), [0u8; 100]).unwrap();
+        temp.check_quota().unwrap();
+
+        fs::write(temp.path().join("large.bin"), [0u8; 2000]).unwrap();
+        match temp.check_quota() {
+            Err(Error::QuotaExceeded {
+                limit,
+                used,
+                largest,
+            }) => {
+                assert_eq!(limit, 1000);
+                assert_eq!(used, 2100);
+                assert_eq!(largest[0], (temp.path().join("large.bin"), 2000));
+                assert_eq!(largest[1], (temp.path().join("small.bin"), 100));
+            }
+            _ => panic!(),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_check_quota_counts_hard_links_once() {
+        let temp = TempDir::builder()
+            .out_dir()
+            .max_bytes(1000)
+            .build()
+            .expect("failed to create temporary directory with quota")
+            .autorm();
+
+        fs::write(temp.path().join("data.bin"), [0u8; 600]).unwrap();
+        fs::hard_link(temp.path().join("data.bin"), temp.path().join("link.bin")).unwrap();
+        temp.check_quota().unwrap();
+        assert_eq!(temp.disk_usage().unwrap().apparent_bytes(), 600);
+    }
+
+    /// Create a directory with a watchdog and wait until it records a violation.
+    fn exceeded_tempdir() -> TempDir {
+        let temp = TempDir::builder()
+            .out_dir()
+            .max_bytes(1000)
+            .quota_watchdog(Duration::from_millis(10))
+            .build()
+            .expect("failed to create temporary directory with quota watchdog")
+            .autorm();
+        fs::write(temp.path().join("large.bin"), [0u8; 2000]).unwrap();
+
+        let watchdog = temp.quota_watchdog.as_ref().unwrap();
+        for _ in 0..500 {
+            if watchdog.exceeded().is_some() {
+                return temp;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("the watchdog did not record the violation");
+    }
+
+    #[test]
+    fn test_watchdog_violation_fails_close() {
+        let temp = exceeded_tempdir();
+        let private_root = temp.removal_target();
+        assert!(matches!(
+            temp.check_quota(),
+            Err(Error::QuotaExceeded { used: 2000, .. })
+        ));
+
+        assert!(matches!(
+            temp.close(),
+            Err(Error::QuotaExceeded { used: 2000, .. })
+        ));
+        assert!(!private_root.try_exists().unwrap());
+    }
+
+    #[test]
+    fn test_watchdog_violation_panics_on_drop() {
+        let temp = exceeded_tempdir();
+        let private_root = temp.removal_target();
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(move || drop(temp)));
+        let message = *result.unwrap_err().downcast::<String>().unwrap();
+        assert!(message.contains("exceeding the quota of 1000 bytes"));
+        assert!(!private_root.try_exists().unwrap());
+    }
+
+    /// Root passed to [`watchdog_exit_child`] when it is run by [`test_watchdog_exits`].
+    const CHILD_ROOT_ENV: &str = "OUTDIR_TEMPDIR_TEST_QUOTA_CHILD_ROOT";
+
+    /// Write past the quota until the watchdog exits the process. Does nothing
+    /// unless run by [`test_watchdog_exits`].
+    #[test]
+    fn watchdog_exit_child() {
+        let Some(root) = std::env::var_os(CHILD_ROOT_ENV) else {
+            return;
+        };
+
+        let temp = TempDir::builder()
+            .path(root)
+            .max_bytes(1000)
+            .quota_watchdog(Duration::from_millis(10))
+            .exit_on_quota_exceeded()
+            .build()
+            .unwrap();
+        for index in 0..1000 {
+            fs::write(temp.path().join(format!("{index}.bin")), [0u8; 100]).unwrap();
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn test_watchdog_exits() {
+        let root = TempDir::new().autorm();
+        let output = std::process::Command::new(std::env::current_exe().unwrap())
+            .args([
+                "--exact",
+                "quota::tests::watchdog_exit_child",
+                "--nocapture",
+            ])
+            .env(CHILD_ROOT_ENV, root.path())
+            .output()
+            .unwrap();
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert_eq!(output.status.code(), Some(101), "{stderr}");
+        assert!(
+            stderr.contains("exceeding the quota of 1000 bytes"),
+            "{stderr}"
+        );
+    }
+
+    #[test]
+    fn test_watchdog_stops_on_drop() {
+        let temp = TempDir::builder()
+            .out_dir()
+            .max_bytes(1000)
+            .quota_watchdog(Duration::from_millis(10))
+            .build()
+            .expect("failed to create temporary directory with quota watchdog")
+            .autorm();
+
+        fs::write(temp.path().join("small.bin"), [0u8; 100]).unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        drop(temp);
+    }
+}
@@ -0,0 +1,505 @@
+use crate::{Error, Result};
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+/// How often removal is attempted before giving up.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RemovalRetry {
+    /// Total number of attempts, including the first one. Always at least 1.
+    pub(crate) attempts: u32,
+
+    /// Delay before the second attempt. The delay doubles after each attempt.
+    pub(crate) backoff: Duration,
+}
+
+impl RemovalRetry {
+    pub(crate) fn new(attempts: u32, backoff: Duration) -> Self {
+        Self {
+            attempts: attempts.max(1),
+            backoff,
+        }
+    }
+
+    /// Run `remove` until it succeeds or the attempts are exhausted.
+    ///
+    /// Only `Io` errors are retried. Any other error is returned immediately,
+    /// since it means removal was refused rather than failed.
+    fn run<F: FnMut() -> Result<()>>(&self, mut remove: F) -> Result<()> {
+        let mut backoff = self.backoff;
+        let mut attempt = 1;
+
+        loop {
+            match remove() {
+                Err(Error::Io(error)) if attempt >= self.attempts => {
+                    return Err(Error::RemovalFailed {
+                        attempts: attempt,
+                        error,
+                    })
+                }
+                Err(Error::Io(_)) => {
+                    thread::sleep(backoff);
+                    backoff = backoff.saturating_mul(2);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+impl Default for RemovalRetry {
+    fn default() -> Self {
+        Self::new(1, Duration::ZERO)
+    }
+}
+
+/// Remove `root/rel` without following symbolic links or crossing mount points.
+///
+/// `rel` must be a cleansed relative path. A missing target is treated as
+/// already removed. If the target or one of its parents below `root` is a
+/// symbolic link, this returns a `SymlinkInRemovalPath` error and nothing is
+/// removed. Failed attempts are retried according to `retry`.
+pub(crate) fn remove_tree(root: &Path, rel: &Path, retry: &RemovalRetry) -> Result<()> {
+    retry.run(|| imp::remove_tree(root, rel))
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    //! Descriptor-relative removal. Every component below the root is opened
+    //! with `O_NOFOLLOW` relative to its parent, so replacing a directory with
+    //! a symbolic link while removal is running cannot redirect it.
+
+    use crate::{Error, Result};
+    use std::ffi::{CStr, CString, OsStr};
+    use std::io;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    struct Fd(libc::c_int);
+
+    impl Drop for Fd {
+        fn drop(&mut self) {
+            unsafe { libc::close(self.0) };
+        }
+    }
+
+    fn cvt(result: libc::c_int) -> io::Result<libc::c_int> {
+        if result == -1 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(result)
+        }
+    }
+
+    fn c_name(name: &OsStr) -> io::Result<CString> {
+        CString::new(name.as_bytes()).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))
+    }
+
+    fn open_dir(path: &Path) -> io::Result<Fd> {
+        let path = c_name(path.as_os_str())?;
+        let flags = libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC;
+        cvt(unsafe { libc::open(path.as_ptr(), flags) }).map(Fd)
+    }
+
+    fn open_dir_at(dir: &Fd, name: &CStr) -> io::Result<Fd> {
+        let flags = libc::O_RDONLY | libc::O_DIRECTORY | libc::O_NOFOLLOW | libc::O_CLOEXEC;
+        cvt(unsafe { libc::openat(dir.0, name.as_ptr(), flags) }).map(Fd)
+    }
+
+    fn stat(dir: &Fd) -> io::Result<libc::stat> {
+        let mut stat = unsafe { std::mem::zeroed::<libc::stat>() };
+        cvt(unsafe { libc::fstat(dir.0, &mut stat) })?;
+        Ok(stat)
+    }
+
+    fn stat_at(dir: &Fd, name: &CStr) -> io::Result<libc::stat> {
+        let mut stat = unsafe { std::mem::zeroed::<libc::stat>() };
+        cvt(unsafe { libc::fstatat(dir.0, name.as_ptr(), &mut stat, libc::AT_SYMLINK_NOFOLLOW) })?;
+        Ok(stat)
+    }
+
+    fn unlink_at(dir: &Fd, name: &CStr, is_dir: bool) -> io::Result<()> {
+        let flags = if is_dir { libc::AT_REMOVEDIR } else { 0 };
+        cvt(unsafe { libc::unlinkat(dir.0, name.as_ptr(), flags) }).map(|_| ())
+    }
+
+    fn is_permission_error(error: &io::Error) -> bool {
+        matches!(error.raw_os_error(), Some(libc::EACCES) | Some(libc::EPERM))
+    }
+
+    /// Give the owner full access to a directory so its entries can be listed and removed.
+    fn make_accessible(dir: &Fd) -> io::Result<()> {
+        let mode = stat(dir)?.st_mode & 0o7777;
+        cvt(unsafe { libc::fchmod(dir.0, mode | 0o700) }).map(|_| ())
+    }
+
+    /// Give the owner full access to a directory entry without following symbolic links.
+    fn make_accessible_at(dir: &Fd, name: &CStr, stat: &libc::stat) -> io::Result<()> {
+        let mode = (stat.st_mode & 0o7777) | 0o700;
+        cvt(unsafe { libc::fchmodat(dir.0, name.as_ptr(), mode, libc::AT_SYMLINK_NOFOLLOW) })
+            .map(|_| ())
+    }
+
+    fn file_type(stat: &libc::stat) -> libc::mode_t {
+        stat.st_mode & libc::S_IFMT
+    }
+
+    fn read_names(dir: &Fd) -> io::Result<Vec<CString>> {
+        // `closedir` closes the descriptor it was given, so hand it a duplicate.
+        let dup = cvt(unsafe { libc::fcntl(dir.0, libc::F_DUPFD_CLOEXEC, 0) })?;
+        let stream = unsafe { libc::fdopendir(dup) };
+        if stream.is_null() {
+            let error = io::Error::last_os_error();
+            unsafe { libc::close(dup) };
+            return Err(error);
+        }
+
+        let mut names = Vec::new();
+        let result = loop {
+            unsafe { *libc::__errno_location() = 0 };
+            let entry = unsafe { libc::readdir(stream) };
+            if entry.is_null() {
+                let error = io::Error::last_os_error();
+                break match error.raw_os_error() {
+                    Some(0) => Ok(names),
+                    _ => Err(error),
+                };
+            }
+
+            let name = unsafe { CStr::from_ptr((*entry).d_name.as_ptr()) };
+            if name.to_bytes() != b"." && name.to_bytes() != b".." {
+                names.push(name.to_owned());
+            }
+        };
+
+        unsafe { libc::closedir(stream) };
+        result
+    }
+
+    /// Check that an entry found below the root may be traversed.
+    fn check_traversable(stat: &libc::stat, dev: libc::dev_t, path: &Path) -> Result<()> {
+        if file_type(stat) == libc::S_IFLNK {
+            return Err(Error::SymlinkInRemovalPath(path.to_path_buf()));
+        }
+
+        if file_type(stat) == libc::S_IFDIR && stat.st_dev != dev {
+            return Err(Error::MountPointInRemovalPath(path.to_path_buf()));
+        }
+
+        Ok(())
+    }
+
+    /// Open a directory that has already been checked with `stat_at`, and make
+    /// sure it is still the same directory.
+    fn open_checked(dir: &Fd, name: &CStr, expected: &libc::stat, path: &Path) -> Result<Fd> {
+        let child = match open_dir_at(dir, name) {
+            Ok(child) => child,
+            Err(error) if error.raw_os_error() == Some(libc::ELOOP) => {
+                return Err(Error::SymlinkInRemovalPath(path.to_path_buf()))
+            }
+            Err(error) => return Err(error.into()),
+        };
+
+        let actual = stat(&child)?;
+        if actual.st_dev != expected.st_dev || actual.st_ino != expected.st_ino {
+            let message = format!("\"{}\" changed during removal", path.display());
+            return Err(io::Error::other(message).into());
+        }
+
+        Ok(child)
+    }
+
+    /// Open a directory that is going to be removed. If it is not readable,
+    /// restore the owner's permissions and try again.
+    fn open_removable(dir: &Fd, name: &CStr, expected: &libc::stat, path: &Path) -> Result<Fd> {
+        match open_checked(dir, name, expected, path) {
+            Err(Error::Io(error)) if is_permission_error(&error) => {
+                make_accessible_at(dir, name, expected)?;
+                open_checked(dir, name, expected, path)
+            }
+            result => result,
+        }
+    }
+
+    /// Remove an entry of a directory that is itself being removed. If the
+    /// directory is read-only, restore the owner's permissions and try again.
+    fn unlink_removable(dir: &Fd, name: &CStr, is_dir: bool) -> io::Result<()> {
+        match unlink_at(dir, name, is_dir) {
+            Err(error) if is_permission_error(&error) => {
+                make_accessible(dir)?;
+                unlink_at(dir, name, is_dir)
+            }
+            result => result,
+        }
+    }
+
+    pub(super) fn remove_tree(root: &Path, rel: &Path) -> Result<()> {
+        let names = rel.iter().map(c_name).collect::<io::Result<Vec<_>>>()?;
+        let Some((last, parents)) = names.split_last() else {
+            return Err(Error::InvalidPath(rel.to_path_buf()));
+        };
+
+        let mut dir = open_dir(root)?;
+        let dev = stat(&dir)?.st_dev;
+        let mut path = root.to_path_buf();
+
+        for (name, component) in parents.iter().zip(rel.iter()) {
+            path.push(component);
+            let child = match stat_at(&dir, name) {
+                Ok(child) => child,
+                Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(()),
+                Err(error) => return Err(error.into()),
+            };
+            check_traversable(&child, dev, &path)?;
+            dir = open_checked(&dir, name, &child, &path)?;
+        }
+
+        path = root.join(rel);
+        let target = match stat_at(&dir, last) {
+            Ok(target) => target,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(error) => return Err(error.into()),
+        };
+        check_traversable(&target, dev, &path)?;
+
+        if file_type(&target) == libc::S_IFDIR {
+            let child = open_removable(&dir, last, &target, &path)?;
+            remove_contents(&child, dev, &path)?;
+            unlink_at(&dir, last, true)?;
+        } else {
+            unlink_at(&dir, last, false)?;
+        }
+
+        Ok(())
+    }
+
+    fn remove_contents(dir: &Fd, dev: libc::dev_t, path: &Path) -> Result<()> {
+        let names = match read_names(dir) {
+            Err(error) if is_permission_error(&error) => {
+                make_accessible(dir)?;
+                read_names(dir)?
+            }
+            result => result?,
+        };
+
+        for name in names {
+            let child_path = path.join(OsStr::from_bytes(name.to_bytes()));
+            let child = match stat_at(dir, &name) {
+                Ok(child) => child,
+                Err(error) if error.kind() == io::ErrorKind::NotFound => continue,
+                Err(error) => return Err(error.into()),
+            };
+
+            if file_type(&child) == libc::S_IFDIR {
+                check_traversable(&child, dev, &child_path)?;
+                let child_dir = open_removable(dir, &name, &child, &child_path)?;
+                remove_contents(&child_dir, dev, &child_path)?;
+                unlink_removable(dir, &name, true)?;
+            } else {
+                // Symbolic links inside the tree are removed, never followed.
+                unlink_removable(dir, &name, false)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod imp {
+    //! Path-based fallback. Parents are checked for symbolic links before
+    //! removal, but there is no protection against concurrent replacement.
+
+    use crate::{Error, Result};
+    use std::fs;
+    use std::io;
+    use std::path::Path;
+
+    pub(super) fn remove_tree(root: &Path, rel: &Path) -> Result<()> {
+        let mut path = root.to_path_buf();
+        let mut metadata = None;
+
+        for component in rel.iter() {
+            path.push(component);
+            let current = match fs::symlink_metadata(&path) {
+                Ok(current) => current,
+                Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(()),
+                Err(error) => return Err(error.into()),
+            };
+
+            if current.file_type().is_symlink() {
+                return Err(Error::SymlinkInRemovalPath(path));
+            }
+            metadata = Some(current);
+        }
+
+        let Some(metadata) = metadata else {
+            return Err(Error::InvalidPath(rel.to_path_buf()));
+        };
+
+        let remove = |path: &Path| {
+            if metadata.is_dir() {
+                fs::remove_dir_all(path)
+            } else {
+                fs::remove_file(path)
+            }
+        };
+
+        let result = match remove(&path) {
+            Err(error) if error.kind() == io::ErrorKind::PermissionDenied => {
+                make_tree_writable(&path)?;
+                remove(&path)
+            }
+            result => result,
+        };
+
+        match result {
+            Ok(()) => Ok(()),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Restore the owner's write permission on everything below `path`,
+    /// without following symbolic links.
+    fn make_tree_writable(path: &Path) -> io::Result<()> {
+        let metadata = fs::symlink_metadata(path)?;
+        if metadata.file_type().is_symlink() {
+            return Ok(());
+        }
+
+        let mut permissions = metadata.permissions();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            permissions.set_mode(permissions.mode() | 0o700);
+        }
+        #[cfg(not(unix))]
+        #[allow(clippy::permissions_set_readonly_false)]
+        permissions.set_readonly(false);
+        fs::set_permissions(path, permissions)?;
+
+        if metadata.is_dir() {
+            for entry in fs::read_dir(path)? {
+                make_tree_writable(&entry?.path())?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod retry_tests {
+    use super::RemovalRetry;
+    use crate::Error;
+    use std::io;
+    use std::time::Duration;
+
+    #[test]
+    fn test_retry_until_success() {
+        let mut calls = 0;
+        let result = RemovalRetry::new(3, Duration::from_millis(1)).run(|| {
+            calls += 1;
+            if calls < 3 {
+                Err(io::Error::from(io::ErrorKind::DirectoryNotEmpty).into())
+            } else {
+                Ok(())
+            }
+        });
+
+        assert!(result.is_ok());
+        assert_eq!(calls, 3);
+    }
+
+    #[test]
+    fn test_retry_reports_attempts() {
+        let mut calls = 0;
+        let result = RemovalRetry::new(2, Duration::from_millis(1)).run(|| {
+            calls += 1;
+            Err(io::Error::from(io::ErrorKind::ResourceBusy).into())
+        });
+
+        match result {
+            Err(Error::RemovalFailed { attempts, error }) => {
+                assert_eq!(attempts, 2);
+                assert_eq!(error.kind(), io::ErrorKind::ResourceBusy);
+            }
+            _ => panic!(),
+        }
+        assert_eq!(calls, 2);
+
+        let mut calls = 0;
+        let result = RemovalRetry::new(5, Duration::from_millis(1)).run(|| {
+            calls += 1;
+            Err(Error::SymlinkInRemovalPath("link".into()))
+        });
+        assert!(matches!(result, Err(Error::SymlinkInRemovalPath(_))));
+        assert_eq!(calls, 1);
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use crate::{Error, TempDir};
+    use std::fs;
+    use std::os::unix::fs::symlink;
+
+    #[test]
+    fn test_close_refuses_symlinked_target() {
+        let outside = TempDir::new().autorm();
+        fs::write(outside.path().join("keep.txt"), "keep").unwrap();
+
+        let temp = TempDir::with_path(format!("test-{}/a/b", uuid::Uuid::new_v4()));
+        let top = temp.root.join(&temp.remove_target_rel);
+        fs::remove_dir_all(&top).unwrap();
+        symlink(outside.path(), &top).unwrap();
+
+        match temp.close() {
+            Err(Error::SymlinkInRemovalPath(path)) => assert_eq!(path, top),
+            _ => panic!(),
+        }
+        assert!(outside.path().join("keep.txt").exists());
+
+        fs::remove_file(&top).unwrap();
+    }
+
+    #[test]
+    fn test_removal_of_read_only_tree() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = TempDir::new();
+        let path = temp.path().to_path_buf();
+        fs::create_dir_all(path.join("read-only/locked")).unwrap();
+        fs::write(path.join("read-only/file.txt"), "file").unwrap();
+        fs::write(path.join("read-only/locked/file.txt"), "file").unwrap();
+        fs::set_permissions(
+            path.join("read-only/locked"),
+            fs::Permissions::from_mode(0o000),
+        )
+        .unwrap();
+        fs::set_permissions(path.join("read-only"), fs::Permissions::from_mode(0o555)).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o555)).unwrap();
+
+        temp.close().unwrap();
+
+        assert!(!path.try_exists().unwrap());
+    }
+
+    #[test]
+    fn test_removal_does_not_follow_inner_symlinks() {
+        let outside = TempDir::new().autorm();
+        fs::write(outside.path().join("keep.txt"), "keep").unwrap();
+
+        let temp = TempDir::new();
+        let path = temp.path().to_path_buf();
+        fs::create_dir(path.join("sub")).unwrap();
+        symlink(outside.path(), path.join("sub/link")).unwrap();
+
+        temp.close().unwrap();
+
+        assert!(!path.try_exists().unwrap());
+        assert!(outside.path().join("keep.txt").exists());
+    }
+}
@@ -0,0 +1,349 @@
+use crate::DiskUsage;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, Write as _};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, OnceLock, PoisonError};
+
+/// Environment variable that enables the cleanup report.
+///
+/// * `stderr` prints a summary to standard error at process exit.
+/// * `json` writes `outdir-tempdir-report-<pid>.json` under
+///   `CARGO_TARGET_TMPDIR` if it is set, or under `OUT_DIR` otherwise.
+/// * `json:<path>` writes the JSON report to `<path>`.
+pub const REPORT_ENV: &str = "OUTDIR_TEMPDIR_REPORT";
+
+/// Where the report is written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Destination {
+    Stderr,
+    Json(PathBuf),
+}
+
+/// Final state of a temporary directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// The `TempDir` value has not been dropped yet.
+    Alive,
+    Removed,
+    Kept,
+    Failed,
+}
+
+impl State {
+    fn as_str(self) -> &'static str {
+        match self {
+            State::Alive => "alive",
+            State::Removed => "removed",
+            State::Kept => "kept",
+            State::Failed => "failed",
+        }
+    }
+}
+
+struct Record {
+    path: PathBuf,
+    root: PathBuf,
+    size: Option<u64>,
+    state: State,
+    error: Option<String>,
+}
+
+static RECORDS: Mutex<Vec<Record>> = Mutex::new(Vec::new());
+
+fn lock_records() -> MutexGuard<'static, Vec<Record>> {
+    RECORDS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Read [`REPORT_ENV`] once. The exit handler is registered on first use.
+fn destination() -> Option<&'static Destination> {
+    static DESTINATION: OnceLock<Option<Destination>> = OnceLock::new();
+
+    DESTINATION
+        .get_or_init(|| {
+            let value = std::env::var_os(REPORT_ENV)?;
+            let destination = match parse_destination(&value.to_string_lossy()) {
+                Ok(destination) => destination?,
+                Err(message) => {
+                    eprintln!("outdir-tempdir: ignoring {REPORT_ENV}: {message}");
+                    return None;
+                }
+            };
+
+            register_exit_handler();
+            Some(destination)
+        })
+        .as_ref()
+}
+
+/// Parse a [`REPORT_ENV`] value. An empty value disables the report.
+fn parse_destination(value: &str) -> Result<Option<Destination>, String> {
+    match value {
+        "" => Ok(None),
+        "stderr" => Ok(Some(Destination::Stderr)),
+        "json" => {
+            let dir = std::env::var_os("CARGO_TARGET_TMPDIR")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| PathBuf::from(std::env!("OUT_DIR")));
+            let name = format!("outdir-tempdir-report-{}.json", std::process::id());
+            Ok(Some(Destination::Json(dir.join(name))))
+        }
+        value => match value.strip_prefix("json:") {
+            Some("") => Err("`json:` needs a path".to_string()),
+            Some(path) => Ok(Some(Destination::Json(PathBuf::from(path)))),
+            None => Err(format!(
+                "unknown value \"{value}\", expected `stderr`, `json` or `json:<path>`"
+            )),
+        },
+    }
+}
+
+/// Register a new temporary directory. Returns its id in the registry, or
+/// `None` if the report is disabled.
+pub(crate) fn register(path: &Path, root: &Path) -> Option<usize> {
+    destination()?;
+
+    let mut records = lock_records();
+    records.push(Record {
+        path: path.to_path_buf(),
+        root: root.to_path_buf(),
+        size: None,
+        state: State::Alive,
+        error: None,
+    });
+    Some(records.len() - 1)
+}
+
+/// Get the apparent size of the tree under `path`, if the report is enabled.
+///
+/// Call this right before removing the tree. Other sizes are measured when
+/// the report is written.
+pub(crate) fn measure(id: Option<usize>, path: &Path) -> Option<u64> {
+    id?;
+    tree_size(path)
+}
+
+/// Record the outcome of dropping or closing a temporary directory.
+pub(crate) fn finish(id: Option<usize>, size: Option<u64>, outcome: Outcome<'_>) {
+    let Some(id) = id else {
+        return;
+    };
+
+    let mut records = lock_records();
+    let record = &mut records[id];
+    record.size = size;
+    match outcome {
+        Outcome::Removed => record.state = State::Removed,
+        Outcome::Kept => record.state = State::Kept,
+        Outcome::Failed(error) => {
+            record.state = State::Failed;
+            record.error = Some(error.to_string());
+        }
+    }
+}
+
+/// Outcome passed to [`finish`].
+pub(crate) enum Outcome<'a> {
+    Removed,
+    Kept,
+    Failed(&'a dyn std::fmt::Display),
+}
+
+fn tree_size(path: &Path) -> Option<u64> {
+    DiskUsage::measure(path)
+        .ok()
+        .map(|usage| usage.apparent_bytes())
+}
+
+/// Write the cleanup report to the destination selected by [`REPORT_ENV`].
+///
+/// The report lists every temporary directory created in this process, its
+/// selected root, its final size, and whether it was removed, kept, failed to
+/// be removed, or is still alive.
+///
+/// On Unix, this is called automatically at process exit when the report is
+/// enabled. On other platforms, call it at the end of the test binary. It
+/// does nothing if [`REPORT_ENV`] is not set.
+///
+/// # Errors
+///
+/// If the JSON report cannot be written, it will lead to an `Io` error.
+pub fn write_cleanup_report() -> io::Result<()> {
+    let Some(destination) = destination() else {
+        return Ok(());
+    };
+
+    let mut records = lock_records();
+    for record in records.iter_mut() {
+        if matches!(record.state, State::Alive | State::Kept) {
+            record.size = tree_size(&record.path);
+        }
+    }
+
+    match destination {
+        Destination::Stderr => io::stderr().write_all(summary(&records).as_bytes()),
+        Destination::Json(path) => {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(path, json(&records))
+        }
+    }
+}
+
+fn summary(records: &[Record]) -> String {
+    let count = |state| records.iter().filter(|r| r.state == state).count();
+    let mut text = format!(
+        "outdir-tempdir: {} temporary directories ({} removed, {} kept, {} failed, {} alive)\n",
+        records.len(),
+        count(State::Removed),
+        count(State::Kept),
+        count(State::Failed),
+        count(State::Alive),
+    );
+
+    for record in records {
+        let size = match record.size {
+            Some(size) => format!("{size} bytes"),
+            None => "-".to_string(),
+        };
+        let _ = write!(
+            text,
+            "  {:<7} {:>14}  {}",
+            record.state.as_str(),
+            size,
+            record.path.display()
+        );
+        if let Some(error) = &record.error {
+            let _ = write!(text, ": {error}");
+        }
+        text.push('\n');
+    }
+
+    text
+}
+
+fn json(records: &[Record]) -> String {
+    let mut text = String::from("[\n");
+    for (index, record) in records.iter().enumerate() {
+        let size = record
+            .size
+            .map_or_else(|| "null".to_string(), |size| size.to_string());
+        let error = record
+            .error
+            .as_deref()
+            .map_or_else(|| "null".to_string(), json_string);
+        let _ = write!(
+            text,
+            "  {{\"path\": {}, \"root\": {}, \"size\": {}, \"state\": \"{}\", \"error\": {}}}",
+            json_string(&record.path.to_string_lossy()),
+            json_string(&record.root.to_string_lossy()),
+            size,
+            record.state.as_str(),
+            error,
+        );
+        text.push_str(if index + 1 < records.len() {
+            ",\n"
+        } else {
+            "\n"
+        });
+    }
+    text.push_str("]\n");
+    text
+}
+
+fn json_string(value: &str) -> String {
+    let mut text = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => text.push_str("\\\""),
+            '\\' => text.push_str("\\\\"),
+            '\n' => text.push_str("\\n"),
+            '\r' => text.push_str("\\r"),
+            '\t' => text.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(text, "\\u{:04x}", c as u32);
+            }
+            c => text.push(c),
+        }
+    }
+    text.push('"');
+    text
+}
+
+#[cfg(unix)]
+fn register_exit_handler() {
+    extern "C" fn write_report_at_exit() {
+        // A panic cannot unwind out of an `extern "C"` function and would
+        // abort the process.
+        let _ = std::panic::catch_unwind(|| {
+            if let Err(error) = write_cleanup_report() {
+                eprintln!("outdir-tempdir: failed to write cleanup report: {error}");
+            }
+        });
+    }
+
+    unsafe { libc::atexit(write_report_at_exit) };
+}
+
+#[cfg(not(unix))]
+fn register_exit_handler() {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(path: &str, size: Option<u64>, state: State, error: Option<&str>) -> Record {
+        Record {
+            path: PathBuf::from(path),
+            root: PathBuf::from("/root"),
+            size,
+            state,
+            error: error.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_report_formats() {
+        let records = [
+            record("/root/test-a", Some(12), State::Removed, None),
+            record("/root/test-\"b\"", None, State::Failed, Some("busy")),
+        ];
+
+        let summary = summary(&records);
+        assert!(summary.starts_with(
+            "outdir-tempdir: 2 temporary directories (1 removed, 0 kept, 1 failed, 0 alive)\n"
+        ));
+        assert!(summary.contains("  removed       12 bytes  /root/test-a\n"));
+        assert!(summary.contains("  failed               -  /root/test-\"b\": busy\n"));
+
+        let parsed: serde_json::Value = serde_json::from_str(&json(&records)).unwrap();
+        assert_eq!(
+            parsed,
+            serde_json::json!([
+                {"path": "/root/test-a", "root": "/root", "size": 12, "state": "removed", "error": null},
+                {"path": "/root/test-\"b\"", "root": "/root", "size": null, "state": "failed", "error": "busy"},
+            ])
+        );
+    }
+
+    #[test]
+    fn test_json_escaping() {
+        let value = "quote \" backslash \\ newline \n tab \t bell \u{7} unicode \u{e9}";
+        let parsed: serde_json::Value = serde_json::from_str(&json_string(value)).unwrap();
+        assert_eq!(parsed, value);
+    }
+
+    #[test]
+    fn test_parse_destination() {
+        assert_eq!(parse_destination(""), Ok(None));
+        assert_eq!(parse_destination("stderr"), Ok(Some(Destination::Stderr)));
+        assert_eq!(
+            parse_destination("json:/tmp/report.json"),
+            Ok(Some(Destination::Json(PathBuf::from("/tmp/report.json"))))
+        );
+        assert!(parse_destination("json:").is_err());
+        assert!(parse_destination("STDERR").is_err());
+        assert!(parse_destination("1").is_err());
+    }
+}
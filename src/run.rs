@@ -0,0 +1,227 @@
+//! Implementation of the `cargo-tempdir-test` Cargo subcommand.
+
+use crate::remove::{remove_tree, RemovalRetry};
+use crate::{ROOTS_ENV, RUN_DIR_ENV};
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode, Stdio};
+use std::time::{SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+/// Directory under the Cargo target directory holding run directories.
+const RUNS_DIR: &str = "tempdir-test";
+
+/// Entry point of the `cargo-tempdir-test` binary.
+pub fn main() -> ExitCode {
+    let args = cargo_test_args(std::env::args_os().skip(1));
+    let cargo = std::env::var_os("CARGO").unwrap_or_else(|| OsString::from("cargo"));
+    let runs = target_dir(&cargo, &args).join(RUNS_DIR);
+
+    let run_dir = match create_run_dir(&runs) {
+        Ok(run_dir) => run_dir,
+        Err(error) => {
+            eprintln!(
+                "outdir-tempdir: failed to create run directory in \"{}\": {error}",
+                runs.display()
+            );
+            return ExitCode::FAILURE;
+        }
+    };
+    eprintln!("outdir-tempdir: run directory {}", run_dir.display());
+
+    let mut command = Command::new(&cargo);
+    command.arg("test").args(args).env(RUN_DIR_ENV, &run_dir);
+    if std::env::var_os(ROOTS_ENV).is_none() {
+        command.env(
+            ROOTS_ENV,
+            format!("env:{RUN_DIR_ENV},cargo-target-tmpdir,out-dir"),
+        );
+    }
+
+    let status = match command.status() {
+        Ok(status) => status,
+        Err(error) => {
+            eprintln!("outdir-tempdir: failed to run cargo test: {error}");
+            let _ = finish_run(&run_dir, false);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match finish_run(&run_dir, status.success()) {
+        Ok(Some(archived)) => eprintln!(
+            "outdir-tempdir: tests failed, run directory kept in {}",
+            archived.display()
+        ),
+        Ok(None) => {}
+        Err(error) => eprintln!(
+            "outdir-tempdir: failed to clean up \"{}\": {error}",
+            run_dir.display()
+        ),
+    }
+
+    match status.code() {
+        Some(code) => ExitCode::from(u8::try_from(code).unwrap_or(1)),
+        None => ExitCode::FAILURE,
+    }
+}
+
+/// Drop the subcommand name that Cargo passes as the first argument.
+fn cargo_test_args<I: Iterator<Item = OsString>>(args: I) -> Vec<OsString> {
+    let mut args = args.peekable();
+    if args.peek().is_some_and(|arg| arg == "tempdir-test") {
+        args.next();
+    }
+    args.collect()
+}
+
+/// Find the target directory of the current workspace.
+///
+/// `--target-dir` and `--manifest-path` in the `cargo test` arguments are
+/// honored. Otherwise the directory is read from `cargo metadata`, which also
+/// takes `CARGO_TARGET_DIR` and `build.target-dir` in Cargo configuration
+/// into account.
+fn target_dir(cargo: &OsStr, args: &[OsString]) -> PathBuf {
+    if let Some(dir) = option_value(args, "--target-dir") {
+        return PathBuf::from(dir);
+    }
+
+    let mut command = Command::new(cargo);
+    command.args(["metadata", "--format-version", "1", "--no-deps"]);
+    if let Some(manifest_path) = option_value(args, "--manifest-path") {
+        command.arg("--manifest-path").arg(manifest_path);
+    }
+
+    let target_directory = command
+        .stderr(Stdio::inherit())
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| serde_json::from_slice::<serde_json::Value>(&output.stdout).ok())
+        .and_then(|metadata| metadata["target_directory"].as_str().map(PathBuf::from));
+
+    target_directory
+        .or_else(|| std::env::var_os("CARGO_TARGET_DIR").map(PathBuf::from))
+        .unwrap_or_else(|| PathBuf::from("target"))
+}
+
+/// Get the value of a Cargo option, given as `--name value` or `--name=value`.
+///
+/// Arguments after `--` belong to the test binaries and are not searched.
+fn option_value(args: &[OsString], name: &str) -> Option<OsString> {
+    let mut args = args.iter().take_while(|arg| *arg != "--");
+    while let Some(arg) = args.next() {
+        if arg == name {
+            return args.next().cloned();
+        }
+
+        let value = arg
+            .to_str()
+            .and_then(|arg| arg.strip_prefix(name))
+            .and_then(|rest| rest.strip_prefix('='));
+        if let Some(value) = value {
+            return Some(OsString::from(value));
+        }
+    }
+    None
+}
+
+/// Create `runs/run-<seconds since the epoch>-<uuid>` and return its absolute path.
+fn create_run_dir(runs: &Path) -> io::Result<PathBuf> {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let run_dir = runs.join(format!("run-{seconds}-{}", Uuid::new_v4()));
+    fs::create_dir_all(&run_dir)?;
+    run_dir.canonicalize()
+}
+
+/// Remove the run directory after a successful run, or move it to `failed/` otherwise.
+///
+/// Returns the new path of an archived run directory.
+fn finish_run(run_dir: &Path, success: bool) -> crate::Result<Option<PathBuf>> {
+    let (Some(runs), Some(name)) = (run_dir.parent(), run_dir.file_name()) else {
+        return Err(crate::Error::InvalidPath(run_dir.to_path_buf()));
+    };
+
+    if success {
+        remove_tree(runs, Path::new(name), &RemovalRetry::default())?;
+        return Ok(None);
+    }
+
+    let failed = runs.join("failed");
+    fs::create_dir_all(&failed)?;
+    let archived = failed.join(name);
+    fs::rename(run_dir, &archived)?;
+    Ok(Some(archived))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TempDir;
+
+    #[test]
+    fn test_cargo_test_args() {
+        let args = |args: &[&str]| cargo_test_args(args.iter().map(OsString::from));
+        assert_eq!(
+            args(&["tempdir-test", "--", "--nocapture"]),
+            ["--", "--nocapture"]
+        );
+        assert_eq!(args(&["-p", "foo"]), ["-p", "foo"]);
+    }
+
+    #[test]
+    fn test_option_value() {
+        let args = |args: &[&str]| args.iter().map(OsString::from).collect::<Vec<_>>();
+        assert_eq!(
+            option_value(&args(&["--target-dir", "out", "-p", "foo"]), "--target-dir"),
+            Some(OsString::from("out"))
+        );
+        assert_eq!(
+            option_value(&args(&["--manifest-path=a/Cargo.toml"]), "--manifest-path"),
+            Some(OsString::from("a/Cargo.toml"))
+        );
+        assert_eq!(
+            option_value(&args(&["--", "--target-dir", "out"]), "--target-dir"),
+            None
+        );
+        assert_eq!(
+            option_value(&args(&["--target-dirs", "out"]), "--target-dir"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_target_dir_from_metadata() {
+        let cargo = std::env::var_os("CARGO").unwrap_or_else(|| OsString::from("cargo"));
+        let manifest = Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml");
+        let args = vec![OsString::from("--manifest-path"), manifest.into()];
+        let expected = std::env::var_os("CARGO_TARGET_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("target"));
+        assert_eq!(
+            target_dir(&cargo, &args).canonicalize().unwrap(),
+            expected.canonicalize().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_finish_run() {
+        let target = TempDir::new().autorm();
+        let runs = target.path().join(RUNS_DIR);
+
+        let passed = create_run_dir(&runs).unwrap();
+        fs::write(passed.join("data.bin"), [0u8; 10]).unwrap();
+        assert_eq!(finish_run(&passed, true).unwrap(), None);
+        assert!(!passed.try_exists().unwrap());
+
+        let failed = create_run_dir(&runs).unwrap();
+        fs::write(failed.join("data.bin"), [0u8; 10]).unwrap();
+        let archived = finish_run(&failed, false).unwrap().unwrap();
+        assert!(!failed.try_exists().unwrap());
+        assert_eq!(archived.parent().unwrap().file_name().unwrap(), "failed");
+        assert!(archived.join("data.bin").is_file());
+    }
+}
@@ -0,0 +1,390 @@
+use crate::walk::walk;
+use crate::Result;
+use std::fmt;
+use std::fs::{self, File, FileType};
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+
+/// Kind of an entry in a [`Snapshot`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    /// A directory.
+    Dir,
+
+    /// A regular file.
+    File,
+
+    /// A symbolic link. The link is recorded but never followed.
+    Symlink,
+
+    /// A named pipe. Its contents are never read.
+    Fifo,
+
+    /// A Unix domain socket.
+    Socket,
+
+    /// A block device. Its contents are never read.
+    BlockDevice,
+
+    /// A character device. Its contents are never read.
+    CharDevice,
+
+    /// Any other kind of entry.
+    Other,
+}
+
+impl EntryKind {
+    /// Classify an entry from its (not followed) file type.
+    pub(crate) fn of(file_type: FileType) -> Self {
+        if file_type.is_symlink() {
+            return EntryKind::Symlink;
+        }
+        if file_type.is_dir() {
+            return EntryKind::Dir;
+        }
+        if file_type.is_file() {
+            return EntryKind::File;
+        }
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::FileTypeExt;
+
+            if file_type.is_fifo() {
+                return EntryKind::Fifo;
+            }
+            if file_type.is_socket() {
+                return EntryKind::Socket;
+            }
+            if file_type.is_block_device() {
+                return EntryKind::BlockDevice;
+            }
+            if file_type.is_char_device() {
+                return EntryKind::CharDevice;
+            }
+        }
+
+        EntryKind::Other
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            EntryKind::Dir => "dir",
+            EntryKind::File => "file",
+            EntryKind::Symlink => "symlink",
+            EntryKind::Fifo => "fifo",
+            EntryKind::Socket => "socket",
+            EntryKind::BlockDevice => "block-device",
+            EntryKind::CharDevice => "char-device",
+            EntryKind::Other => "other",
+        }
+    }
+}
+
+/// A single entry in a [`Snapshot`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotEntry {
+    path: String,
+    kind: EntryKind,
+    size: Option<u64>,
+    hash: Option<u64>,
+    link_target: Option<PathBuf>,
+}
+
+impl SnapshotEntry {
+    /// Get the path relative to the snapshotted directory, using `/` as separator.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Get the kind of this entry.
+    pub fn kind(&self) -> EntryKind {
+        self.kind
+    }
+
+    /// Get the size in bytes. Only regular files have a size.
+    pub fn size(&self) -> Option<u64> {
+        self.size
+    }
+
+    /// Get the FNV-1a 64-bit hash of the file contents, if hashes were requested.
+    pub fn hash(&self) -> Option<u64> {
+        self.hash
+    }
+
+    /// Get the target of a symbolic link.
+    pub fn link_target(&self) -> Option<&Path> {
+        self.link_target.as_deref()
+    }
+}
+
+impl fmt::Display for SnapshotEntry {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            EntryKind::File => {
+                write!(formatter, "file {} {}", self.path, self.size.unwrap_or(0))?;
+                if let Some(hash) = self.hash {
+                    write!(formatter, " fnv1a64:{hash:016x}")?;
+                }
+                Ok(())
+            }
+            EntryKind::Symlink => match &self.link_target {
+                Some(target) => write!(formatter, "symlink {} -> {}", self.path, target.display()),
+                None => write!(formatter, "symlink {}", self.path),
+            },
+            kind => write!(formatter, "{} {}", kind.name(), self.path),
+        }
+    }
+}
+
+/// Deterministic listing of a directory tree.
+///
+/// The [`Display`](fmt::Display) output has one line per entry, sorted by path:
+///
+/// ```text
+/// dir foo
+/// file foo/bar.txt 12
+/// symlink foo/link -> bar.txt
+/// ```
+///
+/// Other entries, such as named pipes and devices, are listed by kind only,
+/// for example `fifo foo/pipe`, and their contents are never read.
+///
+/// File lines end with ` fnv1a64:<hex>` when the snapshot was taken with
+/// [`TempDir::snapshot_with_hashes`](crate::TempDir::snapshot_with_hashes).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    entries: Vec<SnapshotEntry>,
+}
+
+impl Snapshot {
+    pub(crate) fn capture(dir: &Path, with_hashes: bool) -> Result<Self> {
+        let mut entries = Vec::new();
+
+        for entry in walk(dir)? {
+            let kind = EntryKind::of(entry.metadata.file_type());
+            let mut snapshot_entry = SnapshotEntry {
+                path: slash_path(&entry.rel),
+                kind,
+                size: None,
+                hash: None,
+                link_target: None,
+            };
+
+            match kind {
+                EntryKind::Symlink => {
+                    snapshot_entry.link_target = Some(fs::read_link(&entry.full)?);
+                }
+                EntryKind::File => {
+                    snapshot_entry.size = Some(entry.metadata.len());
+                    if with_hashes {
+                        snapshot_entry.hash = Some(hash_file(&entry.full)?);
+                    }
+                }
+                _ => {}
+            }
+
+            entries.push(snapshot_entry);
+        }
+
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(Self { entries })
+    }
+
+    /// Get the entries of this snapshot, sorted by path.
+    pub fn entries(&self) -> &[SnapshotEntry] {
+        &self.entries
+    }
+}
+
+impl fmt::Display for Snapshot {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        for entry in &self.entries {
+            writeln!(formatter, "{entry}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Join the normal components of `path` with `/` regardless of the platform.
+pub(crate) fn slash_path(path: &Path) -> String {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(x) => Some(x.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Hash the contents of a file with 64-bit FNV-1a.
+pub(crate) fn hash_file(path: &Path) -> io::Result<u64> {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    let mut file = File::open(path)?;
+    let mut buffer = [0u8; 8192];
+    let mut hash = OFFSET_BASIS;
+
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(hash);
+        }
+
+        for byte in &buffer[..read] {
+            hash ^= u64::from(*byte);
+            hash = hash.wrapping_mul(PRIME);
+        }
+    }
+}
+
+/// Compare a listing against an expected listing and panic with a line diff on mismatch.
+///
+/// Leading and trailing whitespace on each line and blank lines are ignored,
+/// so the expected listing can be written as an indented string literal.
+#[doc(hidden)]
+#[track_caller]
+pub fn assert_tree_eq_impl(actual: &dyn fmt::Display, expected: &str) {
+    let actual = actual.to_string();
+    let actual = normalized_lines(&actual);
+    let expected = normalized_lines(expected);
+
+    if actual != expected {
+        panic!(
+            "directory tree does not match (-expected +actual):\n{}",
+            line_diff(&expected, &actual)
+        );
+    }
+}
+
+fn normalized_lines(text: &str) -> Vec<&str> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+/// Build a readable line diff based on the longest common subsequence.
+fn line_diff(expected: &[&str], actual: &[&str]) -> String {
+    let (n, m) = (expected.len(), actual.len());
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if expected[i] == actual[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && expected[i] == actual[j] {
+            diff.push_str(&format!("  {}\n", expected[i]));
+            i += 1;
+            j += 1;
+        } else if j < m && (i == n || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            diff.push_str(&format!("+ {}\n", actual[j]));
+            j += 1;
+        } else {
+            diff.push_str(&format!("- {}\n", expected[i]));
+            i += 1;
+        }
+    }
+
+    diff
+}
+
+/// Assert that a directory tree matches an expected listing.
+///
+/// The first argument is anything implementing [`Display`](std::fmt::Display),
+/// usually a [`Snapshot`]. The expected listing uses the same format as
+/// [`Snapshot`]'s `Display` output. Surrounding whitespace and blank lines are
+/// ignored. On mismatch, the macro panics with a line diff.
+///
+/// ```no_run
+/// # use outdir_tempdir::*;
+/// let dir = TempDir::new().autorm();
+/// std::fs::create_dir(dir.path().join("foo")).unwrap();
+/// std::fs::write(dir.path().join("foo/bar.txt"), "hello").unwrap();
+///
+/// assert_tree_eq!(
+///     dir.snapshot().unwrap(),
+///     "
+///     dir foo
+///     file foo/bar.txt 5
+///     "
+/// );
+/// ```
+#[macro_export]
+macro_rules! assert_tree_eq {
+    ($actual:expr, $expected:expr $(,)?) => {
+        $crate::__assert_tree_eq(&$actual, $expected)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::TempDir;
+    use std::fs;
+
+    #[test]
+    fn test_snapshot_listing() {
+        let temp = TempDir::new().autorm();
+        fs::create_dir_all(temp.path().join("foo/bar")).unwrap();
+        fs::write(temp.path().join("foo/b.txt"), "hello").unwrap();
+        fs::write(temp.path().join("a.txt"), "").unwrap();
+
+        assert_tree_eq!(
+            temp.snapshot().unwrap(),
+            "
+            file a.txt 0
+            dir foo
+            file foo/b.txt 5
+            dir foo/bar
+            "
+        );
+
+        assert_tree_eq!(
+            temp.snapshot_with_hashes().unwrap(),
+            "
+            file a.txt 0 fnv1a64:cbf29ce484222325
+            dir foo
+            file foo/b.txt 5 fnv1a64:a430d84680aabd0b
+            dir foo/bar
+            "
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_snapshot_does_not_read_fifo() {
+        use std::ffi::CString;
+        use std::os::unix::ffi::OsStrExt;
+
+        let temp = TempDir::new().autorm();
+        let fifo = CString::new(temp.path().join("pipe").as_os_str().as_bytes()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(fifo.as_ptr(), 0o600) }, 0);
+
+        assert_tree_eq!(temp.snapshot_with_hashes().unwrap(), "fifo pipe");
+    }
+
+    #[test]
+    fn test_assert_tree_eq_reports_diff() {
+        let temp = TempDir::new().autorm();
+        fs::write(temp.path().join("a.txt"), "abc").unwrap();
+        let snapshot = temp.snapshot().unwrap();
+
+        let result = std::panic::catch_unwind(|| {
+            assert_tree_eq!(snapshot, "file a.txt 4\nfile b.txt 1");
+        });
+
+        let message = *result.unwrap_err().downcast::<String>().unwrap();
+        assert!(message.contains("- file a.txt 4\n"));
+        assert!(message.contains("- file b.txt 1\n"));
+        assert!(message.contains("+ file a.txt 3\n"));
+    }
+}
@@ -0,0 +1,185 @@
+use crate::event::{self, Event};
+use crate::PendingRemoval;
+use std::collections::VecDeque;
+use std::fs;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use uuid::Uuid;
+
+struct Queue {
+    pending: VecDeque<PendingRemoval>,
+    active: usize,
+    worker_started: bool,
+}
+
+static QUEUE: Mutex<Queue> = Mutex::new(Queue {
+    pending: VecDeque::new(),
+    active: 0,
+    worker_started: false,
+});
+static DONE: Condvar = Condvar::new();
+
+fn lock_queue() -> MutexGuard<'static, Queue> {
+    QUEUE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Move `root/rel` to a hidden `.trash-<uuid>` sibling, and point `removal` at it.
+///
+/// The path reported in events stays the original one. If the rename fails,
+/// the caller should remove the directory synchronously instead.
+pub(crate) fn move_to_trash(removal: &mut PendingRemoval) -> io::Result<()> {
+    let trash = PathBuf::from(format!(".trash-{}", Uuid::new_v4()));
+    fs::rename(removal.root.join(&removal.rel), removal.root.join(&trash))?;
+    removal.rel = trash;
+    event::emit(Event::Deferred {
+        path: &removal.path,
+    });
+    Ok(())
+}
+
+/// Remove a directory moved with [`move_to_trash`] on a background thread.
+///
+/// The outcome is recorded once the directory is actually removed.
+pub(crate) fn defer_removal(removal: PendingRemoval) {
+    let mut queue = lock_queue();
+    queue.pending.push_back(removal);
+    DONE.notify_all();
+
+    if !queue.worker_started {
+        queue.worker_started = true;
+        register_exit_handler();
+        // Without a worker, pending removals still run at exit or in
+        // `wait_for_deferred_removals`.
+        let _ = thread::Builder::new()
+            .name("outdir-tempdir-trash".into())
+            .spawn(worker);
+    }
+}
+
+fn worker() {
+    loop {
+        let mut queue = lock_queue();
+        while queue.pending.is_empty() {
+            queue = DONE.wait(queue).unwrap_or_else(PoisonError::into_inner);
+        }
+
+        let pending = queue.pending.pop_front();
+        queue.active += 1;
+        drop(queue);
+
+        if let Some(pending) = pending {
+            remove(pending);
+        }
+
+        lock_queue().active -= 1;
+        DONE.notify_all();
+    }
+}
+
+/// Remove a pending directory. A panic, for example in an event handler, is
+/// caught so that it neither stops the worker nor unwinds out of the exit handler.
+fn remove(pending: PendingRemoval) {
+    let trash = pending.root.join(&pending.rel);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| pending.remove()));
+    if let Ok(Err(error)) = result {
+        eprintln!(
+            "outdir-tempdir: failed to remove \"{}\": {error}",
+            trash.display()
+        );
+    }
+}
+
+/// Block until every directory scheduled with [`TempDir::autorm_deferred`](crate::TempDir::autorm_deferred)
+/// has been removed.
+///
+/// Pending removals are also finished at process exit on Unix. Call this
+/// function explicitly on other platforms, or when a test needs the disk space
+/// back before continuing.
+pub fn wait_for_deferred_removals() {
+    loop {
+        let pending = lock_queue().pending.pop_front();
+        match pending {
+            Some(pending) => remove(pending),
+            None => break,
+        }
+    }
+
+    let mut queue = lock_queue();
+    while queue.active > 0 {
+        queue = DONE.wait(queue).unwrap_or_else(PoisonError::into_inner);
+    }
+}
+
+#[cfg(unix)]
+fn register_exit_handler() {
+    extern "C" fn finish_deferred_removals() {
+        // A panic cannot unwind out of an `extern "C"` function and would
+        // abort the process. Locks already ignore poisoning.
+        let _ = std::panic::catch_unwind(wait_for_deferred_removals);
+    }
+
+    unsafe { libc::atexit(finish_deferred_removals) };
+}
+
+#[cfg(not(unix))]
+fn register_exit_handler() {}
+
+#[cfg(test)]
+mod tests {
+    use super::wait_for_deferred_removals;
+    use crate::event::HANDLER_TEST_LOCK;
+    use crate::{clear_event_handler, set_event_handler, Event, TempDir};
+    use std::fs;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_autorm_deferred() {
+        let parent = TempDir::new().autorm();
+
+        let path = {
+            let temp = TempDir::builder()
+                .path(parent.path())
+                .build_with_path("a/b")
+                .expect("failed to create temporary directory with builder")
+                .autorm_deferred();
+            fs::write(temp.path().join("data.bin"), [0u8; 1024]).unwrap();
+            temp.path().to_path_buf()
+        };
+
+        assert!(!path.try_exists().unwrap());
+
+        wait_for_deferred_removals();
+        assert_eq!(fs::read_dir(parent.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_deferred_events() {
+        static EVENTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+        let _lock = HANDLER_TEST_LOCK.lock();
+
+        let parent = TempDir::new().autorm();
+        let filter = parent.path().canonicalize().unwrap();
+        set_event_handler(move |event| {
+            let line = match event {
+                Event::Deferred { path } if path.starts_with(&filter) => "deferred",
+                Event::Removed { path } if path.starts_with(&filter) => "removed",
+                _ => return,
+            };
+            EVENTS.lock().unwrap().push(line.to_string());
+        });
+
+        let temp = TempDir::builder()
+            .path(parent.path())
+            .build()
+            .unwrap()
+            .autorm_deferred();
+        drop(temp);
+        wait_for_deferred_removals();
+        clear_event_handler();
+
+        assert_eq!(*EVENTS.lock().unwrap(), ["deferred", "removed"]);
+    }
+}
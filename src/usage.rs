@@ -0,0 +1,149 @@
+use crate::walk::walk;
+use crate::Result;
+use std::collections::HashSet;
+use std::fs::Metadata;
+use std::path::Path;
+
+/// Disk usage of a directory tree.
+///
+/// Created by [`TempDir::disk_usage`](crate::TempDir::disk_usage). The tree
+/// is walked without following symbolic links, and the top directory itself
+/// is not counted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DiskUsage {
+    apparent_bytes: u64,
+    allocated_bytes: u64,
+    files: u64,
+    dirs: u64,
+    symlinks: u64,
+}
+
+impl DiskUsage {
+    pub(crate) fn measure(dir: &Path) -> Result<Self> {
+        Self::measure_files(dir, |_, _| {})
+    }
+
+    /// Same as [`DiskUsage::measure`], also calling `on_file` with the path and
+    /// length of every file counted in [`DiskUsage::apparent_bytes`].
+    pub(crate) fn measure_files<F: FnMut(&Path, u64)>(dir: &Path, mut on_file: F) -> Result<Self> {
+        let mut usage = Self::default();
+        let mut seen = HashSet::new();
+
+        for entry in walk(dir)? {
+            let metadata = &entry.metadata;
+            let file_type = metadata.file_type();
+
+            if file_type.is_symlink() {
+                usage.symlinks += 1;
+            } else if file_type.is_dir() {
+                usage.dirs += 1;
+            } else {
+                usage.files += 1;
+                // Hard links share their data, so count it only once.
+                if !first_link(metadata, &mut seen) {
+                    continue;
+                }
+                usage.apparent_bytes += metadata.len();
+                on_file(&entry.full, metadata.len());
+            }
+
+            usage.allocated_bytes += allocated(metadata);
+        }
+
+        Ok(usage)
+    }
+
+    /// Get the total length of regular files in bytes.
+    pub fn apparent_bytes(&self) -> u64 {
+        self.apparent_bytes
+    }
+
+    /// Get the space allocated on disk for files, directories and symbolic links in bytes.
+    ///
+    /// On platforms other than Unix, this is the same as [`DiskUsage::apparent_bytes`].
+    pub fn allocated_bytes(&self) -> u64 {
+        self.allocated_bytes
+    }
+
+    /// Get the number of regular files.
+    pub fn files(&self) -> u64 {
+        self.files
+    }
+
+    /// Get the number of directories.
+    pub fn dirs(&self) -> u64 {
+        self.dirs
+    }
+
+    /// Get the number of symbolic links.
+    pub fn symlinks(&self) -> u64 {
+        self.symlinks
+    }
+}
+
+#[cfg(unix)]
+fn first_link(metadata: &Metadata, seen: &mut HashSet<(u64, u64)>) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    metadata.nlink() <= 1 || seen.insert((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn first_link(_metadata: &Metadata, _seen: &mut HashSet<(u64, u64)>) -> bool {
+    true
+}
+
+#[cfg(unix)]
+fn allocated(metadata: &Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+
+    // `st_blocks` is always in 512-byte units, regardless of the block size.
+    metadata.blocks() * 512
+}
+
+#[cfg(not(unix))]
+fn allocated(metadata: &Metadata) -> u64 {
+    if metadata.is_file() {
+        metadata.len()
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::TempDir;
+    use std::fs;
+
+    #[test]
+    fn test_disk_usage() {
+        let temp = TempDir::new().autorm();
+        fs::create_dir_all(temp.path().join("a/b")).unwrap();
+        fs::write(temp.path().join("a/one.bin"), [0u8; 100]).unwrap();
+        fs::write(temp.path().join("a/b/two.bin"), [0u8; 4000]).unwrap();
+
+        let usage = temp.disk_usage().unwrap();
+        assert_eq!(usage.files(), 2);
+        assert_eq!(usage.dirs(), 2);
+        assert_eq!(usage.symlinks(), 0);
+        assert_eq!(usage.apparent_bytes(), 4100);
+        assert!(usage.allocated_bytes() > 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_disk_usage_counts_hard_links_once_and_ignores_symlinks() {
+        let outside = TempDir::new().autorm();
+        fs::write(outside.path().join("big.bin"), [0u8; 8192]).unwrap();
+
+        let temp = TempDir::new().autorm();
+        fs::write(temp.path().join("data.bin"), [0u8; 10]).unwrap();
+        fs::hard_link(temp.path().join("data.bin"), temp.path().join("link.bin")).unwrap();
+        std::os::unix::fs::symlink(outside.path(), temp.path().join("outside")).unwrap();
+
+        let usage = temp.disk_usage().unwrap();
+        assert_eq!(usage.files(), 2);
+        assert_eq!(usage.symlinks(), 1);
+        assert_eq!(usage.apparent_bytes(), 10);
+    }
+}
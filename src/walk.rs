@@ -0,0 +1,49 @@
+use std::fs::{self, Metadata};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Entry found while walking a directory tree.
+pub(crate) struct WalkEntry {
+    /// Path relative to the walked directory.
+    pub(crate) rel: PathBuf,
+
+    /// Path including the walked directory.
+    pub(crate) full: PathBuf,
+
+    /// Metadata of the entry itself (symbolic links are not followed).
+    pub(crate) metadata: Metadata,
+}
+
+/// Walk the directory tree under `dir` without following symbolic links.
+///
+/// Entries are returned depth-first, with siblings sorted by file name, so the
+/// order is deterministic. `dir` itself is not included.
+pub(crate) fn walk(dir: &Path) -> io::Result<Vec<WalkEntry>> {
+    let mut entries = Vec::new();
+    walk_into(dir, Path::new(""), &mut entries)?;
+    Ok(entries)
+}
+
+fn walk_into(dir: &Path, rel: &Path, entries: &mut Vec<WalkEntry>) -> io::Result<()> {
+    let mut children = fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+    children.sort_by_key(|child| child.file_name());
+
+    for child in children {
+        let full = child.path();
+        let rel = rel.join(child.file_name());
+        let metadata = fs::symlink_metadata(&full)?;
+        let is_dir = metadata.is_dir();
+
+        entries.push(WalkEntry {
+            rel: rel.clone(),
+            full: full.clone(),
+            metadata,
+        });
+
+        if is_dir {
+            walk_into(&full, &rel, entries)?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,52 @@
+//! Tests that change the process current directory. They live in their own
+//! test binary so that no unrelated test observes the change.
+
+use outdir_tempdir::{Error, TempDir};
+use std::env;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Serialises the tests in this binary. `enter` only serialises the guards,
+/// while these tests also read the current directory outside of them.
+static CWD_TEST_LOCK: Mutex<()> = Mutex::new(());
+
+fn lock_cwd() -> MutexGuard<'static, ()> {
+    CWD_TEST_LOCK.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[test]
+fn enter_restores_current_dir() {
+    let _lock = lock_cwd();
+    let temp = TempDir::new().autorm();
+    let before = env::current_dir().unwrap();
+
+    {
+        let guard = temp.enter().unwrap();
+        assert_eq!(guard.previous(), before);
+        assert_eq!(
+            env::current_dir().unwrap(),
+            temp.path().canonicalize().unwrap()
+        );
+    }
+
+    assert_eq!(env::current_dir().unwrap(), before);
+}
+
+#[test]
+fn enter_twice_on_same_thread_fails() {
+    let _lock = lock_cwd();
+    let first = TempDir::new().autorm();
+    let second = TempDir::new().autorm();
+
+    let guard = first.enter().unwrap();
+    assert!(matches!(
+        second.enter(),
+        Err(Error::CurrentDirAlreadyEntered)
+    ));
+    assert!(matches!(
+        first.enter(),
+        Err(Error::CurrentDirAlreadyEntered)
+    ));
+    drop(guard);
+
+    drop(second.enter().unwrap());
+}
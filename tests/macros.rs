@@ -0,0 +1,53 @@
+use outdir_tempdir::TempDir;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+static KEPT: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+#[outdir_tempdir::test]
+fn receives_path(dir: &Path) {
+    assert!(dir.ends_with("receives_path"));
+    assert!(dir.is_dir());
+    fs::write(dir.join("output.txt"), "ok").unwrap();
+}
+
+#[outdir_tempdir::test(builder = TempDir::builder().out_dir())]
+fn receives_tempdir_with_custom_builder(dir: TempDir) {
+    let private_root = dir.path().parent().unwrap();
+    assert!(private_root
+        .file_name()
+        .unwrap()
+        .to_string_lossy()
+        .starts_with("test-"));
+    assert!(dir.path().ends_with("receives_tempdir_with_custom_builder"));
+}
+
+#[outdir_tempdir::test]
+fn receives_tempdir_reference(dir: &TempDir) {
+    assert!(dir.path().is_dir());
+}
+
+#[test]
+fn keep_on_failure_removes_directory_without_panic() {
+    let path = {
+        let dir = TempDir::new().keep_on_failure();
+        dir.path().to_path_buf()
+    };
+    assert!(!path.try_exists().unwrap());
+}
+
+#[test]
+fn keep_on_failure_keeps_directory_on_panic() {
+    let result = std::thread::spawn(|| {
+        let dir = TempDir::new().keep_on_failure();
+        *KEPT.lock().unwrap() = Some(dir.path().to_path_buf());
+        panic!("intentional failure");
+    })
+    .join();
+    assert!(result.is_err());
+
+    let path = KEPT.lock().unwrap().take().unwrap();
+    assert!(path.is_dir());
+    fs::remove_dir_all(path).unwrap();
+}